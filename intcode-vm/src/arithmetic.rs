@@ -0,0 +1,99 @@
+//! How a [VM](crate::vm::IntcodeVM) handles arithmetic overflows
+//! (see [`vm.set_overflow_policy()`](crate::vm::IntcodeVM::set_overflow_policy))
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{arithmetic::OverflowPolicy, error::VMErrorKind, instruction::Opcode, IntcodeVM, VMResult};
+//! // outputs i64::MAX + 1
+//! let program = [1101, i64::MAX, 1, 7, 4, 7, 99, 0];
+//!
+//! let mut vm = IntcodeVM::new(program);
+//! assert!(matches!(
+//!     vm.run().map_err(|error| error.kind),
+//!     Err(VMErrorKind::ArithmeticOverflow { ip: 0, opcode: Opcode::Add })
+//! ));
+//!
+//! let mut vm = IntcodeVM::new(program);
+//! vm.set_overflow_policy(OverflowPolicy::Wrapping);
+//! assert_eq!(vm.run().unwrap(), VMResult::Output(i64::MIN));
+//!
+//! let mut vm = IntcodeVM::new(program);
+//! vm.set_overflow_policy(OverflowPolicy::Saturating);
+//! assert_eq!(vm.run().unwrap(), VMResult::Output(i64::MAX));
+//! ```
+
+use num::Integer;
+#[cfg(feature = "bigint")]
+use num_bigint::{BigInt, BigUint};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What a VM does when the result of an `ADD`, a `MUL` or an `ARB` (relative base update)
+/// does not fit in the type of its values
+///
+/// Whatever the build profile, so a program always behaves the same.
+/// A relative address (relative base + argument) that does not fit is always an
+/// [`ArithmeticOverflow`](crate::error::VMErrorKind::ArithmeticOverflow), whatever the policy:
+/// a wrapped or saturated address would point to an unrelated value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OverflowPolicy {
+    /// Fail with [`VMErrorKind::ArithmeticOverflow`](crate::error::VMErrorKind::ArithmeticOverflow)
+    #[default]
+    Checked,
+    /// Wrap around at the bounds of the type
+    Wrapping,
+    /// Stop at the bounds of the type
+    Saturating,
+}
+
+/// Arithmetic the VM performs on its values, following an [OverflowPolicy]
+///
+/// Implemented for every primitive integer type, and for `BigInt` and `BigUint` with the `bigint` feature.
+/// The default methods never overflow, so a type without bounds only needs an empty `impl`.
+pub trait OverflowArithmetic: Integer + Clone {
+    /// Returns `self + rhs`, [`None`] if it overflowed with [`OverflowPolicy::Checked`]
+    #[inline]
+    fn add_with(&self, rhs: &Self, _policy: OverflowPolicy) -> Option<Self> {
+        Some(self.clone() + rhs.clone())
+    }
+
+    /// Returns `self * rhs`, [`None`] if it overflowed with [`OverflowPolicy::Checked`]
+    #[inline]
+    fn mul_with(&self, rhs: &Self, _policy: OverflowPolicy) -> Option<Self> {
+        Some(self.clone() * rhs.clone())
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl OverflowArithmetic for BigInt {}
+
+#[cfg(feature = "bigint")]
+impl OverflowArithmetic for BigUint {}
+
+macro_rules! impl_overflow_arithmetic {
+    ($($t:ty)*) => {$(
+        impl OverflowArithmetic for $t {
+            #[inline]
+            fn add_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self> {
+                match policy {
+                    OverflowPolicy::Checked => self.checked_add(*rhs),
+                    OverflowPolicy::Wrapping => Some(self.wrapping_add(*rhs)),
+                    OverflowPolicy::Saturating => Some(self.saturating_add(*rhs)),
+                }
+            }
+
+            #[inline]
+            fn mul_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self> {
+                match policy {
+                    OverflowPolicy::Checked => self.checked_mul(*rhs),
+                    OverflowPolicy::Wrapping => Some(self.wrapping_mul(*rhs)),
+                    OverflowPolicy::Saturating => Some(self.saturating_mul(*rhs)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_overflow_arithmetic!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
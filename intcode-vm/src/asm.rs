@@ -0,0 +1,514 @@
+//! Assembler turning mnemonic source code into a [Memory]
+//!
+//! The syntax is the one produced by the [disassembler](crate::disasm),
+//! so assembling a listing gives back the exact program it was made from:
+//!
+//! - one statement per line, `;` starts a comment running until the end of the line
+//! - a statement can be preceded by labels (`loop:`), a label is a name starting with a letter or `_`
+//!   and its value is the address of what follows it.
+//!   A number followed by a colon (`0012:`) is an address annotation: it is checked against the current address
+//! - instructions are written `MNEMONIC args -> destination` (or `MNEMONIC args, destination`),
+//!   mnemonics are case insensitive:
+//!
+//! | Opcode | Mnemonic | Arguments          |
+//! |--------|----------|--------------------|
+//! | `01`   | `ADD`    | `a, b -> dest`     |
+//! | `02`   | `MUL`    | `a, b -> dest`     |
+//! | `03`   | `IN`     | `-> dest`          |
+//! | `04`   | `OUT`    | `a`                |
+//! | `05`   | `JNZ`    | `condition, target`|
+//! | `06`   | `JZ`     | `condition, target`|
+//! | `07`   | `LT`     | `a, b -> dest`     |
+//! | `08`   | `EQ`     | `a, b -> dest`     |
+//! | `09`   | `ARB`    | `a`                |
+//! | `99`   | `HLT`    |                    |
+//!
+//! - arguments are written `#value` (immediate), `[value]` (positional)
+//!   or `[rb+value]` / `[rb-value]` (relative, the brackets are optional), where a value is a number,
+//!   a label or a label followed by `+number` or `-number`
+//! - `DATA value, value, ...` stores the values as is
+//! - `RESERVE count` stores `count` zeros
+//!
+//! A program larger than [MAX_CONTIGUOUS_SIZE] values is assembled into a [paged](Memory::into_paged)
+//! memory where `RESERVE` zeros are not stored, so sparse listings assemble back as well.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{asm, IntcodeVM};
+//! let memory = asm::assemble::<i64>(
+//!     "
+//!     ; outputs 10, 9, ..., 1
+//!     loop:   OUT [counter]
+//!             ADD [counter], #-1 -> [counter]
+//!             JNZ [counter], #loop
+//!             HLT
+//!     counter: DATA 10
+//!     ",
+//! )
+//! .unwrap();
+//!
+//! let mut vm = IntcodeVM::new(memory);
+//! assert_eq!(vm.run_until_halt().unwrap(), vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+//! ```
+
+use std::{collections::HashMap, str::FromStr};
+
+use num::{CheckedAdd, CheckedSub, FromPrimitive, Integer, ToPrimitive};
+
+use crate::{
+    error::{AsmError, AsmErrorKind},
+    instruction::{ArgMode, Opcode},
+    memory::Memory,
+};
+
+/// Maximum number of values, `RESERVE` included, of a program assembled into a contiguous memory
+pub const MAX_CONTIGUOUS_SIZE: usize = 1 << 24;
+
+/// A piece of a line, with the column (starting at 1) it starts at
+#[derive(Debug, Clone, Copy)]
+struct Token<'s> {
+    text: &'s str,
+    column: usize,
+}
+
+impl<'s> Token<'s> {
+    /// Removes the whitespaces around the token, keeping the column up to date
+    #[inline]
+    fn trim(self) -> Self {
+        let start = self.text.len() - self.text.trim_start().len();
+        Self {
+            text: self.text.trim(),
+            column: self.column + start,
+        }
+    }
+
+    /// Splits the token at byte `index`, `index` itself belongs to neither part
+    #[inline]
+    fn split_around(self, index: usize) -> (Self, Self) {
+        (
+            Self {
+                text: &self.text[..index],
+                column: self.column,
+            },
+            Self {
+                text: &self.text[index + 1..],
+                column: self.column + index + 1,
+            },
+        )
+    }
+
+    #[inline]
+    fn error(&self, line: usize, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            line,
+            column: self.column,
+            kind,
+        }
+    }
+}
+
+/// A value that may depend on the address of a label
+#[derive(Debug, Clone)]
+struct Expr<'s, T> {
+    label: Option<Token<'s>>,
+    offset: T,
+    /// Only set for labels, numbers are parsed with their sign
+    negated: bool,
+}
+
+#[derive(Debug, Clone)]
+enum StatementKind<'s, T> {
+    Instruction {
+        opcode: Opcode,
+        args: Vec<(ArgMode, Expr<'s, T>)>,
+    },
+    Data(Vec<Expr<'s, T>>),
+    Reserve(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Statement<'s, T> {
+    line: usize,
+    kind: StatementKind<'s, T>,
+}
+
+impl<T> Statement<'_, T> {
+    #[inline]
+    fn width(&self) -> usize {
+        match &self.kind {
+            StatementKind::Instruction { args, .. } => args.len() + 1,
+            StatementKind::Data(values) => values.len(),
+            StatementKind::Reserve(count) => *count,
+        }
+    }
+}
+
+/// Assembles `source` into a [Memory] (see the [module documentation](self) for the syntax)
+///
+/// # Errors
+///
+/// Returns an [AsmError] pointing at the line and column of the first problem found
+///
+/// ```
+/// # use intcode_vm::{asm, error::AsmErrorKind};
+/// let err = asm::assemble::<i64>("OUT #1\nJZ #0, #end\nHLT").unwrap_err();
+///
+/// assert_eq!((err.line, err.column), (2, 9));
+/// assert_eq!(err.kind, AsmErrorKind::UndefinedLabel("end".to_string()));
+/// ```
+pub fn assemble<T>(source: &str) -> Result<Memory<T>, AsmError>
+where
+    T: Integer + Clone + ToPrimitive + FromPrimitive + FromStr + CheckedAdd + CheckedSub,
+{
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (line, text) in source.lines().enumerate() {
+        let line = line + 1;
+        let code = text.find(';').map_or(text, |comment| &text[..comment]);
+        let mut rest = Token {
+            text: code,
+            column: 1,
+        }
+        .trim();
+
+        while let Some((label, after)) = split_label(rest) {
+            if label.text.bytes().all(|b| b.is_ascii_digit()) {
+                let annotated = label.text.parse().map_err(|_| {
+                    label.error(line, AsmErrorKind::InvalidValue(label.text.to_string()))
+                })?;
+
+                if annotated != address {
+                    return Err(label.error(
+                        line,
+                        AsmErrorKind::AddressMismatch {
+                            annotated,
+                            actual: address,
+                        },
+                    ));
+                }
+            } else if labels.insert(label.text, address).is_some() {
+                return Err(label.error(line, AsmErrorKind::DuplicateLabel(label.text.to_string())));
+            }
+
+            rest = after.trim();
+        }
+
+        if rest.text.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(rest, line)?;
+        address = address
+            .checked_add(statement.width())
+            .ok_or_else(|| rest.error(line, AsmErrorKind::ProgramTooLarge(usize::MAX)))?;
+        statements.push(statement);
+    }
+
+    let contiguous = address <= MAX_CONTIGUOUS_SIZE;
+    let capacity = if contiguous { address } else { 0 };
+    let mut segments = vec![(0, Vec::with_capacity(capacity))];
+    for statement in statements {
+        let (start, memory) = segments.last_mut().expect("there is always a segment");
+        let line = statement.line;
+        match statement.kind {
+            StatementKind::Instruction { opcode, args } => {
+                let modes = args.iter().map(|(mode, _)| *mode).collect::<Vec<_>>();
+                let op = opcode.encode(&modes);
+                memory.push(T::from_u16(op).ok_or_else(|| AsmError {
+                    line,
+                    column: 1,
+                    kind: AsmErrorKind::ValueOutOfRange(op.to_string()),
+                })?);
+
+                for (_, expr) in args {
+                    memory.push(resolve(expr, &labels, line)?);
+                }
+            }
+            StatementKind::Data(values) => {
+                for expr in values {
+                    memory.push(resolve(expr, &labels, line)?);
+                }
+            }
+            StatementKind::Reserve(count) if contiguous => {
+                memory.resize(memory.len() + count, T::zero())
+            }
+            StatementKind::Reserve(count) => {
+                let next = *start + memory.len() + count;
+                segments.push((next, Vec::new()));
+            }
+        }
+    }
+
+    if contiguous {
+        let (_, memory) = segments.swap_remove(0);
+        return Ok(memory.into());
+    }
+
+    let mut memory = Memory::from(Vec::new()).into_paged();
+    for (start, values) in segments {
+        for (offset, value) in values.into_iter().enumerate() {
+            memory.set(start + offset, value);
+        }
+    }
+    // keeps the zeros reserved at the end
+    if memory.len() < address {
+        memory.set(address - 1, T::zero());
+    }
+
+    Ok(memory)
+}
+
+/// Splits `name:` at the start of `token`, if there is one
+fn split_label(token: Token<'_>) -> Option<(Token<'_>, Token<'_>)> {
+    let colon = token.text.find(':')?;
+    let (label, rest) = token.split_around(colon);
+    let is_word = !label.text.is_empty()
+        && label
+            .text
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_');
+
+    is_word.then_some((label, rest))
+}
+
+fn parse_statement<T>(token: Token<'_>, line: usize) -> Result<Statement<'_, T>, AsmError>
+where
+    T: Integer + Clone + FromStr,
+{
+    let (mnemonic, operands) = match token.text.find(char::is_whitespace) {
+        Some(space) => {
+            let (mnemonic, operands) = token.split_around(space);
+            (mnemonic, operands.trim())
+        }
+        None => (
+            token,
+            Token {
+                text: "",
+                column: token.column + token.text.len(),
+            },
+        ),
+    };
+
+    let kind = if mnemonic.text.eq_ignore_ascii_case("DATA") {
+        StatementKind::Data(
+            split_list(operands)
+                .into_iter()
+                .map(|value| parse_expr(value, line, false))
+                .collect::<Result<_, _>>()?,
+        )
+    } else if mnemonic.text.eq_ignore_ascii_case("RESERVE") {
+        let count = operands.text.parse().map_err(|_| {
+            operands.error(line, AsmErrorKind::InvalidValue(operands.text.to_string()))
+        })?;
+        StatementKind::Reserve(count)
+    } else {
+        let opcode = Opcode::from_mnemonic(mnemonic.text).ok_or_else(|| {
+            mnemonic.error(
+                line,
+                AsmErrorKind::UnknownMnemonic(mnemonic.text.to_string()),
+            )
+        })?;
+
+        let args = match operands.text.find("->") {
+            Some(arrow) => {
+                if opcode.destination_arg().is_none() {
+                    return Err(mnemonic.error(
+                        line,
+                        AsmErrorKind::UnexpectedDestination(mnemonic.text.to_string()),
+                    ));
+                }
+
+                let (args, _) = operands.split_around(arrow);
+                let destination = Token {
+                    text: &operands.text[arrow + 2..],
+                    column: operands.column + arrow + 2,
+                };
+
+                let mut args = split_list(args);
+                args.push(destination.trim());
+                args
+            }
+            None => split_list(operands),
+        };
+
+        if args.len() != opcode.arg_count() {
+            return Err(mnemonic.error(
+                line,
+                AsmErrorKind::WrongArgCount {
+                    mnemonic: mnemonic.text.to_string(),
+                    expected: opcode.arg_count(),
+                    found: args.len(),
+                },
+            ));
+        }
+
+        StatementKind::Instruction {
+            opcode,
+            args: args
+                .into_iter()
+                .map(|arg| parse_arg(arg, line))
+                .collect::<Result<_, _>>()?,
+        }
+    };
+
+    Ok(Statement { line, kind })
+}
+
+/// Splits a comma separated list, an empty token being an empty list
+fn split_list(token: Token<'_>) -> Vec<Token<'_>> {
+    let mut items = Vec::new();
+    if token.trim().text.is_empty() {
+        return items;
+    }
+
+    let mut rest = token;
+    while let Some(comma) = rest.text.find(',') {
+        let (item, after) = rest.split_around(comma);
+        items.push(item.trim());
+        rest = after;
+    }
+
+    items.push(rest.trim());
+    items
+}
+
+fn parse_arg<T>(token: Token<'_>, line: usize) -> Result<(ArgMode, Expr<'_, T>), AsmError>
+where
+    T: Integer + Clone + FromStr,
+{
+    let invalid = || token.error(line, AsmErrorKind::InvalidArgument(token.text.to_string()));
+
+    if let Some(value) = token.text.strip_prefix('#') {
+        let value = Token {
+            text: value,
+            column: token.column + 1,
+        };
+
+        return Ok((ArgMode::Immediate, parse_expr(value.trim(), line, false)?));
+    }
+
+    let inner = match token.text.strip_prefix('[') {
+        Some(inner) => Token {
+            text: inner.strip_suffix(']').ok_or_else(invalid)?,
+            column: token.column + 1,
+        }
+        .trim(),
+        None => token,
+    };
+
+    let offset = inner.text.strip_prefix("rb").map(|offset| {
+        Token {
+            text: offset,
+            column: inner.column + 2,
+        }
+        .trim()
+    });
+
+    let Some(offset) = offset.filter(|offset| {
+        offset.text.is_empty() || offset.text.starts_with('+') || offset.text.starts_with('-')
+    }) else {
+        return Ok((ArgMode::Positional, parse_expr(inner, line, false)?));
+    };
+
+    if offset.text.is_empty() {
+        return Ok((
+            ArgMode::Relative,
+            Expr {
+                label: None,
+                offset: T::zero(),
+                negated: false,
+            },
+        ));
+    }
+
+    let negated = offset.text.starts_with('-');
+    let (_, expr) = offset.split_around(0);
+    Ok((ArgMode::Relative, parse_expr(expr.trim(), line, negated)?))
+}
+
+/// Parses a number or a label with an optional offset, `negated` if it is preceded by a `-`
+fn parse_expr<T>(token: Token<'_>, line: usize, negated: bool) -> Result<Expr<'_, T>, AsmError>
+where
+    T: Integer + Clone + FromStr,
+{
+    let invalid = || token.error(line, AsmErrorKind::InvalidValue(token.text.to_string()));
+    let starts_label = token
+        .text
+        .bytes()
+        .next()
+        .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_');
+
+    if !starts_label {
+        return Ok(Expr {
+            label: None,
+            offset: parse_number(token.text, negated).ok_or_else(invalid)?,
+            negated: false,
+        });
+    }
+
+    let end = token
+        .text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(token.text.len());
+    let label = Token {
+        text: &token.text[..end],
+        column: token.column,
+    };
+
+    if label.text == "rb" {
+        return Err(label.error(line, AsmErrorKind::InvalidLabel(label.text.to_string())));
+    }
+
+    let offset = token.text[end..].trim();
+    let offset = match offset.as_bytes().first() {
+        None => T::zero(),
+        Some(b'+') => parse_number(&offset[1..], false).ok_or_else(invalid)?,
+        Some(b'-') => parse_number(&offset[1..], true).ok_or_else(invalid)?,
+        Some(_) => return Err(invalid()),
+    };
+
+    Ok(Expr {
+        label: Some(label),
+        offset,
+        negated,
+    })
+}
+
+/// Parses `digits`, with a `-` sign if `negative`, so the most negative value of `T` can be written
+fn parse_number<T: FromStr>(digits: &str, negative: bool) -> Option<T> {
+    let digits = digits.trim();
+    if !negative {
+        digits.parse().ok()
+    } else if digits.starts_with(['+', '-']) {
+        None
+    } else {
+        format!("-{digits}").parse().ok()
+    }
+}
+
+fn resolve<T>(expr: Expr<'_, T>, labels: &HashMap<&str, usize>, line: usize) -> Result<T, AsmError>
+where
+    T: Integer + Clone + FromPrimitive + CheckedAdd + CheckedSub,
+{
+    let Some(label) = expr.label else {
+        return Ok(expr.offset);
+    };
+
+    let address = labels
+        .get(label.text)
+        .ok_or_else(|| label.error(line, AsmErrorKind::UndefinedLabel(label.text.to_string())))?;
+
+    let out_of_range = || label.error(line, AsmErrorKind::ValueOutOfRange(label.text.to_string()));
+    let value = T::from_usize(*address)
+        .and_then(|address| address.checked_add(&expr.offset))
+        .ok_or_else(out_of_range)?;
+
+    if expr.negated {
+        T::zero().checked_sub(&value).ok_or_else(out_of_range)
+    } else {
+        Ok(value)
+    }
+}
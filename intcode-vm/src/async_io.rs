@@ -0,0 +1,156 @@
+//! Asynchronous inputs and outputs, to run [VMs](crate::vm::IntcodeVM) concurrently
+//! (see [`vm.run_async()`](crate::vm::IntcodeVM::run_async))
+//!
+//! The VM does not depend on any async runtime: [AsyncInputSource] and [AsyncOutputSink]
+//! are implemented for the channels of [`futures::channel::mpsc`],
+//! and for the ones of [`tokio::sync::mpsc`](https://docs.rs/tokio/latest/tokio/sync/mpsc/index.html)
+//! with the `tokio` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # use futures::{channel::mpsc, executor::block_on, future::join};
+//! # use intcode_vm::{IntcodeVM, StopReason};
+//! // outputs twice its input, until it reads 0
+//! let program = [3, 15, 1006, 15, 14, 102, 2, 15, 15, 4, 15, 1105, 1, 0, 99, 0];
+//! let mut first = IntcodeVM::new(program);
+//! let mut second = IntcodeVM::new(program);
+//!
+//! let (input, first_input) = mpsc::unbounded();
+//! let (first_output, second_input) = mpsc::unbounded();
+//! let (second_output, mut output) = mpsc::unbounded();
+//! for value in [1, 2, 3, 0] {
+//!     input.unbounded_send(value).unwrap();
+//! }
+//!
+//! let (first_stop, second_stop) = block_on(join(
+//!     first.run_async(first_input, first_output),
+//!     second.run_async(second_input, second_output),
+//! ));
+//! assert_eq!(first_stop.unwrap(), StopReason::Halted);
+//! // the channel between them was closed when the first one halted
+//! assert_eq!(second_stop.unwrap(), StopReason::WaitingForInput);
+//!
+//! let outputs = std::iter::from_fn(|| output.try_recv().ok()).collect::<Vec<_>>();
+//! assert_eq!(outputs, vec![4, 8, 12]);
+//! ```
+
+use std::future::Future;
+
+use futures::{
+    channel::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender},
+    SinkExt, StreamExt,
+};
+
+/// Provides input values to a [VM](crate::vm::IntcodeVM) running asynchronously
+/// (see [`vm.run_async()`](crate::vm::IntcodeVM::run_async))
+///
+/// Implemented for the receivers of channels and mutable references to an implementor
+pub trait AsyncInputSource<T> {
+    /// Waits for the next input value, returns [`None`] if no value will ever be available
+    /// (e.g. every sender of the channel was dropped)
+    ///
+    /// When this returns [`None`], the VM stops with [`StopReason::WaitingForInput`](crate::vm::StopReason::WaitingForInput)
+    fn next_input(&mut self) -> impl Future<Output = Option<T>>;
+}
+
+/// Receives the values output by a [VM](crate::vm::IntcodeVM) running asynchronously
+/// (see [`vm.run_async()`](crate::vm::IntcodeVM::run_async))
+///
+/// Implemented for [`Vec`], the senders of channels and mutable references to an implementor
+pub trait AsyncOutputSink<T> {
+    /// Called with every value output by the VM, in order, waiting until it can be sent
+    ///
+    /// Values which cannot be sent anymore (e.g. the receiver of the channel was dropped) are discarded.
+    fn write_output(&mut self, value: T) -> impl Future<Output = ()>;
+}
+
+impl<T, I> AsyncInputSource<T> for &mut I
+where
+    I: AsyncInputSource<T> + ?Sized,
+{
+    #[inline]
+    fn next_input(&mut self) -> impl Future<Output = Option<T>> {
+        (**self).next_input()
+    }
+}
+
+impl<T, S> AsyncOutputSink<T> for &mut S
+where
+    S: AsyncOutputSink<T> + ?Sized,
+{
+    #[inline]
+    fn write_output(&mut self, value: T) -> impl Future<Output = ()> {
+        (**self).write_output(value)
+    }
+}
+
+impl<T> AsyncOutputSink<T> for Vec<T> {
+    #[inline]
+    async fn write_output(&mut self, value: T) {
+        self.push(value);
+    }
+}
+
+impl<T> AsyncInputSource<T> for Receiver<T> {
+    #[inline]
+    async fn next_input(&mut self) -> Option<T> {
+        self.next().await
+    }
+}
+
+impl<T> AsyncInputSource<T> for UnboundedReceiver<T> {
+    #[inline]
+    async fn next_input(&mut self) -> Option<T> {
+        self.next().await
+    }
+}
+
+impl<T> AsyncOutputSink<T> for Sender<T> {
+    #[inline]
+    async fn write_output(&mut self, value: T) {
+        let _ = self.send(value).await;
+    }
+}
+
+impl<T> AsyncOutputSink<T> for UnboundedSender<T> {
+    #[inline]
+    async fn write_output(&mut self, value: T) {
+        let _ = self.unbounded_send(value);
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod tokio_adapter {
+    use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+
+    use super::{AsyncInputSource, AsyncOutputSink};
+
+    impl<T> AsyncInputSource<T> for Receiver<T> {
+        #[inline]
+        async fn next_input(&mut self) -> Option<T> {
+            self.recv().await
+        }
+    }
+
+    impl<T> AsyncInputSource<T> for UnboundedReceiver<T> {
+        #[inline]
+        async fn next_input(&mut self) -> Option<T> {
+            self.recv().await
+        }
+    }
+
+    impl<T> AsyncOutputSink<T> for Sender<T> {
+        #[inline]
+        async fn write_output(&mut self, value: T) {
+            let _ = self.send(value).await;
+        }
+    }
+
+    impl<T> AsyncOutputSink<T> for UnboundedSender<T> {
+        #[inline]
+        async fn write_output(&mut self, value: T) {
+            let _ = self.send(value);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Arbitrary-precision values (requires the `bigint` feature)
+//!
+//! [`IntcodeVM<BigInt>`](crate::vm::IntcodeVM) never overflows, whatever its
+//! [OverflowPolicy](crate::arithmetic::OverflowPolicy), and addresses are still
+//! cast with [`to_usize()`](num::ToPrimitive::to_usize).
+//! [PromotingIntcodeVM] runs with `i64` values as long as they are big enough,
+//! and switches to `BigInt` values the first time an operation overflows.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{bigint::BigInt, PromotingIntcodeVM, VMResult};
+//! let mut vm: PromotingIntcodeVM = "1102,9223372036854775807,10,7,4,7,99,0".parse().unwrap();
+//!
+//! let expected: BigInt = "92233720368547758070".parse().unwrap();
+//! assert_eq!(vm.run().unwrap(), VMResult::Output(expected));
+//! assert!(vm.is_promoted());
+//! ```
+
+use std::str::FromStr;
+
+use num::ToPrimitive;
+pub use num_bigint::BigInt;
+
+use crate::{
+    error::{self, VMError, VMErrorKind},
+    memory::Memory,
+    vm::{IntcodeVM, StopReason, VMResult},
+};
+
+/// The VM wrapped by a [PromotingIntcodeVM]
+#[derive(Debug, Clone)]
+enum Inner {
+    I64(IntcodeVM<i64>),
+    BigInt(IntcodeVM<BigInt>),
+}
+
+/// A VM running with `i64` values until an `ADD`, a `MUL` or an `ARB` overflows,
+/// then with `BigInt` values
+///
+/// The instruction which overflowed is executed again once the values are promoted,
+/// so the program behaves as if it always ran with `BigInt` values,
+/// only faster as long as its values fit in an `i64`.
+/// Inputs that do not fit in an `i64` promote the values too.
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::{bigint::BigInt, PromotingIntcodeVM};
+/// // doubles its input and outputs it, forever
+/// let mut vm = PromotingIntcodeVM::new([3, 13, 102, 2, 13, 13, 4, 13, 1105, 1, 2, 99, 0, 0]);
+/// vm.push_input(i64::MAX / 3);
+///
+/// let outputs = (0..3).map(|_| vm.run().unwrap()).collect::<Vec<_>>();
+/// assert!(vm.is_promoted());
+/// assert_eq!(
+///     outputs,
+///     [2, 4, 8].map(|factor| intcode_vm::VMResult::Output(BigInt::from(i64::MAX / 3) * factor))
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct PromotingIntcodeVM {
+    inner: Inner,
+    /// Kept to be given to the `BigInt` VM when the values are promoted
+    run_budget: Option<u64>,
+}
+
+impl PromotingIntcodeVM {
+    /// Creates a new VM from the given [`memory`](Memory), running with `i64` values
+    #[inline]
+    pub fn new<I: Into<Memory<i64>>>(memory: I) -> Self {
+        Self {
+            inner: Inner::I64(IntcodeVM::new(memory)),
+            run_budget: None,
+        }
+    }
+
+    /// Runs the VM until it encounters an output, halts or needs an input
+    ///
+    /// Same as [`IntcodeVM::run()`], except it never fails with [`VMErrorKind::ArithmeticOverflow`].
+    pub fn run(&mut self) -> error::Result<VMResult<BigInt>, BigInt> {
+        let vm = match &mut self.inner {
+            Inner::BigInt(vm) => return vm.run(),
+            Inner::I64(vm) => vm,
+        };
+
+        let run_start = vm.executed_instructions();
+        match vm.run() {
+            Err(VMError {
+                kind: VMErrorKind::ArithmeticOverflow { .. },
+                ..
+            }) => {
+                let executed = vm.executed_instructions() - run_start;
+                self.promote();
+                self.finish_run(executed, IntcodeVM::run)
+            }
+            result => result
+                .map(promote_result)
+                .map_err(|error| error.map(BigInt::from)),
+        }
+    }
+
+    /// Runs the VM until it halts, collecting every value it outputs along the way
+    ///
+    /// Same as [`IntcodeVM::run_until_halt()`].
+    pub fn run_until_halt(&mut self) -> error::Result<Vec<BigInt>, BigInt> {
+        match self.run_until_input()? {
+            (outputs, StopReason::Halted) => Ok(outputs),
+            (outputs, StopReason::WaitingForInput) => {
+                Err(self.with_context(VMErrorKind::MissingInput { outputs }.into()))
+            }
+            (outputs, reason) => {
+                Err(self.with_context(VMErrorKind::Stopped { reason, outputs }.into()))
+            }
+        }
+    }
+
+    /// Runs the VM until it halts or stops for another reason (see [StopReason]),
+    /// collecting every value it outputs along the way
+    ///
+    /// Same as [`IntcodeVM::run_until_input()`].
+    pub fn run_until_input(&mut self) -> error::Result<(Vec<BigInt>, StopReason), BigInt> {
+        let vm = match &mut self.inner {
+            Inner::BigInt(vm) => return vm.run_until_input(),
+            Inner::I64(vm) => vm,
+        };
+
+        // a single iterator, so the whole call is a single run for the run budget
+        let run_start = vm.executed_instructions();
+        let mut outputs = Vec::new();
+        let mut iter = vm.outputs();
+        for output in iter.by_ref() {
+            match output {
+                Ok(value) => outputs.push(BigInt::from(value)),
+                Err(VMError {
+                    kind: VMErrorKind::ArithmeticOverflow { .. },
+                    ..
+                }) => break,
+                Err(error) => return Err(error.map(BigInt::from)),
+            }
+        }
+
+        if let Some(reason) = iter.stop_reason() {
+            return Ok((outputs, reason));
+        }
+
+        let executed = vm.executed_instructions() - run_start;
+        self.promote();
+        let (promoted_outputs, reason) = self.finish_run(executed, IntcodeVM::run_until_input)?;
+        outputs.extend(promoted_outputs);
+        Ok((outputs, reason))
+    }
+
+    /// Adds `input` at the end of the input queue, promoting the values if it does not fit in an `i64`
+    pub fn push_input<V: Into<BigInt>>(&mut self, input: V) {
+        let input = input.into();
+        if let Inner::I64(vm) = &mut self.inner {
+            match input.to_i64() {
+                Some(input) => return vm.push_input(input),
+                None => self.promote(),
+            }
+        }
+
+        if let Inner::BigInt(vm) = &mut self.inner {
+            vm.push_input(input);
+        }
+    }
+
+    /// Adds every value of `inputs` at the end of the input queue, in order
+    pub fn extend_inputs<V: Into<BigInt>, I: IntoIterator<Item = V>>(&mut self, inputs: I) {
+        for input in inputs {
+            self.push_input(input);
+        }
+    }
+
+    /// Limits the number of instructions a single run can execute, [`None`] to remove the limit
+    ///
+    /// Same as [`IntcodeVM::set_run_budget()`]: the instructions executed before and after
+    /// the values are promoted count against the budget of the same run.
+    #[inline]
+    pub fn set_run_budget(&mut self, budget: Option<u64>) {
+        self.run_budget = budget;
+        match &mut self.inner {
+            Inner::I64(vm) => vm.set_run_budget(budget),
+            Inner::BigInt(vm) => vm.set_run_budget(budget),
+        }
+    }
+
+    /// Returns the number of values in the input queue
+    #[inline]
+    pub fn pending_inputs(&self) -> usize {
+        match &self.inner {
+            Inner::I64(vm) => vm.pending_inputs(),
+            Inner::BigInt(vm) => vm.pending_inputs(),
+        }
+    }
+
+    /// Returns `true` once the VM runs with `BigInt` values
+    #[inline]
+    pub const fn is_promoted(&self) -> bool {
+        matches!(self.inner, Inner::BigInt(_))
+    }
+
+    /// Returns the address of the next instruction to be executed
+    #[inline]
+    pub const fn instruction_ptr(&self) -> usize {
+        match &self.inner {
+            Inner::I64(vm) => vm.instruction_ptr(),
+            Inner::BigInt(vm) => vm.instruction_ptr(),
+        }
+    }
+
+    /// Returns the value of the relative base
+    #[inline]
+    pub fn relative_base(&self) -> BigInt {
+        match &self.inner {
+            Inner::I64(vm) => BigInt::from(*vm.relative_base()),
+            Inner::BigInt(vm) => vm.relative_base().clone(),
+        }
+    }
+
+    /// Returns the value at `address` in the memory of the VM
+    #[inline]
+    pub fn get(&self, address: usize) -> BigInt {
+        match &self.inner {
+            Inner::I64(vm) => BigInt::from(*vm.memory().get(address)),
+            Inner::BigInt(vm) => vm.memory().get(address).clone(),
+        }
+    }
+
+    /// Returns the VM running with `BigInt` values, promoting them if needed
+    pub fn into_bigint(mut self) -> IntcodeVM<BigInt> {
+        self.promote();
+        match self.inner {
+            Inner::BigInt(vm) => vm,
+            Inner::I64(_) => unreachable!("the VM was just promoted"),
+        }
+    }
+
+    /// Returns the memory of the VM
+    #[inline]
+    pub fn into_memory(self) -> Memory<BigInt> {
+        self.into_bigint().into_memory()
+    }
+
+    /// Adds the current state of the VM to `error`
+    #[cold]
+    fn with_context(&self, error: VMError<BigInt>) -> VMError<BigInt> {
+        error.with_context(self.instruction_ptr(), self.relative_base(), |address| {
+            self.get(address)
+        })
+    }
+
+    /// Finishes a run with `run` on the promoted VM, after `executed` instructions of it
+    /// were executed with `i64` values
+    fn finish_run<R, F>(&mut self, executed: u64, run: F) -> R
+    where
+        F: FnOnce(&mut IntcodeVM<BigInt>) -> R,
+    {
+        let Inner::BigInt(vm) = &mut self.inner else {
+            unreachable!("the VM was promoted");
+        };
+
+        vm.set_run_budget(
+            self.run_budget
+                .map(|budget| budget.saturating_sub(executed)),
+        );
+        let result = run(vm);
+        vm.set_run_budget(self.run_budget);
+        result
+    }
+
+    /// Switches to `BigInt` values, keeping the state of the VM
+    fn promote(&mut self) {
+        if let Inner::I64(vm) = &self.inner {
+            let mut vm = IntcodeVM::restore(vm.snapshot().map(BigInt::from))
+                .expect("the snapshot was just taken");
+            vm.set_run_budget(self.run_budget);
+            self.inner = Inner::BigInt(vm);
+        }
+    }
+}
+
+impl<I> From<I> for PromotingIntcodeVM
+where
+    I: Into<Memory<i64>>,
+{
+    #[inline]
+    fn from(memory: I) -> Self {
+        Self::new(memory)
+    }
+}
+
+impl FromStr for PromotingIntcodeVM {
+    type Err = <BigInt as FromStr>::Err;
+
+    /// Parses a comma separated list of values (see [`Memory::from_str()`]),
+    /// starting with `BigInt` values if one of them does not fit in an `i64`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let memory = s.parse::<Memory<BigInt>>()?;
+        let inner = match memory
+            .iter()
+            .map(BigInt::to_i64)
+            .collect::<Option<Memory<i64>>>()
+        {
+            Some(memory) => Inner::I64(IntcodeVM::new(memory)),
+            None => Inner::BigInt(IntcodeVM::new(memory)),
+        };
+        Ok(Self {
+            inner,
+            run_budget: None,
+        })
+    }
+}
+
+fn promote_result(result: VMResult<i64>) -> VMResult<BigInt> {
+    match result {
+        VMResult::Halted => VMResult::Halted,
+        VMResult::WaitingForInput => VMResult::WaitingForInput,
+        VMResult::Output(value) => VMResult::Output(value.into()),
+        VMResult::Breakpoint { address, reason } => VMResult::Breakpoint { address, reason },
+        VMResult::BudgetExhausted => VMResult::BudgetExhausted,
+    }
+}
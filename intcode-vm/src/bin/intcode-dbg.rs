@@ -0,0 +1,577 @@
+//! Interactive debugger for Intcode programs
+//!
+//! Usage: `intcode-dbg <program>`, where `<program>` is either a comma separated list of values
+//! (like the puzzle inputs) or an assembly listing if its name ends with `.asm`
+//! (see [`intcode_vm::asm`]).
+//!
+//! Type `help` at the prompt to list the commands, an empty line repeats the previous command.
+
+use std::{
+    collections::VecDeque,
+    env,
+    error::Error,
+    fs,
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use intcode_vm::{
+    asm,
+    breakpoint::{BreakReason, WatchKind},
+    disasm::{Disassembler, Line, LineContent},
+    instruction::{Opcode, Step},
+    memory::Memory,
+    observer::Observer,
+    IntcodeVM, VMResult,
+};
+
+const HELP: &str = "\
+Commands:
+  s, step [n]                execute the next n instructions (default: 1)
+  c, continue                run until the program halts, needs an input or hits a breakpoint
+                             (pauses every 10000000 instructions)
+  b, break <addr>            stop before executing the instruction at addr
+  d, delete <addr>           remove the breakpoint at addr
+  w, watch <addr> [kind]     stop after an instruction accesses addr, kind is read, write (default) or change
+  unwatch <addr> [kind]      remove a watchpoint
+  x, mem <addr> [count]      print count values starting at addr (default: 16)
+  set <addr> <value>         write value at addr
+  i, input <value>...        queue input values
+  o, outputs                 print every value output so far
+  l, list [n]                disassemble n lines around the instruction pointer (default: 5)
+  info                       print the registers, breakpoints and watchpoints
+  reset                      reload the program, keeping the breakpoints and watchpoints
+  h, help                    print this message
+  q, quit                    exit the debugger";
+
+/// Number of values per line printed by the `mem` command
+const VALUES_PER_LINE: usize = 8;
+
+/// Number of instructions `continue` executes before giving the prompt back,
+/// so a program stuck in an infinite loop can still be inspected
+const CONTINUE_BUDGET: u64 = 10_000_000;
+
+/// Number of values of the widest instruction, used to find where to start decoding a listing
+const MAX_INSTRUCTION_WIDTH: usize = 4;
+
+/// Records every value output by the program, whether it was `step`ped or `continue`d
+#[derive(Debug, Clone, Default)]
+struct OutputLog {
+    outputs: Vec<i64>,
+}
+
+impl Observer<i64> for OutputLog {
+    #[inline]
+    fn on_output(&mut self, value: &i64) {
+        self.outputs.push(*value);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Step(usize),
+    Continue,
+    Break(usize),
+    Delete(usize),
+    Watch(usize, WatchKind),
+    Unwatch(usize, WatchKind),
+    Inspect(usize, usize),
+    Set(usize, i64),
+    Input(Vec<i64>),
+    Outputs,
+    List(usize),
+    Info,
+    Reset,
+    Help,
+    Quit,
+}
+
+/// Parses the next word of a command, or returns `default` if there is none
+fn parse_arg<T: FromStr>(arg: Option<&str>, name: &str, default: Option<T>) -> Result<T, String> {
+    match (arg, default) {
+        (Some(arg), _) => arg
+            .parse()
+            .map_err(|_| format!("Invalid {}: `{}`", name, arg)),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err(format!("Missing {}", name)),
+    }
+}
+
+fn parse_watch_kind(arg: Option<&str>) -> Result<WatchKind, String> {
+    match arg {
+        None | Some("w" | "write") => Ok(WatchKind::Write),
+        Some("r" | "read") => Ok(WatchKind::Read),
+        Some("c" | "change") => Ok(WatchKind::Change),
+        Some(other) => Err(format!(
+            "Invalid watchpoint kind: `{}` (expected read, write or change)",
+            other
+        )),
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err(String::from("Empty command"));
+        };
+
+        let command = match name {
+            "s" | "step" => Self::Step(parse_arg(words.next(), "step count", Some(1))?),
+            "c" | "continue" => Self::Continue,
+            "b" | "break" => Self::Break(parse_arg(words.next(), "address", None)?),
+            "d" | "delete" => Self::Delete(parse_arg(words.next(), "address", None)?),
+            "w" | "watch" => Self::Watch(
+                parse_arg(words.next(), "address", None)?,
+                parse_watch_kind(words.next())?,
+            ),
+            "unwatch" => Self::Unwatch(
+                parse_arg(words.next(), "address", None)?,
+                parse_watch_kind(words.next())?,
+            ),
+            "x" | "mem" => Self::Inspect(
+                parse_arg(words.next(), "address", None)?,
+                parse_arg(words.next(), "count", Some(2 * VALUES_PER_LINE))?,
+            ),
+            "set" => Self::Set(
+                parse_arg(words.next(), "address", None)?,
+                parse_arg(words.next(), "value", None)?,
+            ),
+            "i" | "input" => {
+                let inputs = words
+                    .by_ref()
+                    .map(|word| parse_arg(Some(word), "input", None))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if inputs.is_empty() {
+                    return Err(String::from("Missing input value(s)"));
+                }
+
+                Self::Input(inputs)
+            }
+            "o" | "outputs" => Self::Outputs,
+            "l" | "list" => Self::List(parse_arg(words.next(), "line count", Some(5))?),
+            "info" => Self::Info,
+            "reset" => Self::Reset,
+            "h" | "help" => Self::Help,
+            "q" | "quit" => Self::Quit,
+            other => return Err(format!("Unknown command `{}` (try `help`)", other)),
+        };
+
+        match words.next() {
+            Some(extra) => Err(format!("Unexpected argument `{}`", extra)),
+            None => Ok(command),
+        }
+    }
+}
+
+fn describe_break(address: usize, reason: BreakReason) -> String {
+    match reason {
+        BreakReason::Breakpoint => format!("Breakpoint at {:04}", address),
+        BreakReason::Watchpoint {
+            address: watched,
+            kind,
+        } => format!(
+            "Watchpoint ({:?}) on {} hit by the instruction at {:04}",
+            kind, watched, address
+        ),
+        BreakReason::Condition(id) => format!("Condition {:?} became true at {:04}", id, address),
+    }
+}
+
+struct Debugger {
+    program: Memory<i64>,
+    vm: IntcodeVM<i64, OutputLog>,
+    /// Number of outputs already printed
+    shown_outputs: usize,
+}
+
+impl Debugger {
+    fn new(program: Memory<i64>) -> Self {
+        // paged, so `set` on a far away address does not allocate everything before it
+        let mut vm =
+            IntcodeVM::new(program.clone().into_paged()).with_observer(OutputLog::default());
+        vm.set_run_budget(Some(CONTINUE_BUDGET));
+
+        Self {
+            vm,
+            program,
+            shown_outputs: 0,
+        }
+    }
+
+    /// Runs `command`, returns `false` if the debugger should exit
+    fn execute(&mut self, command: Command) -> bool {
+        match command {
+            Command::Step(count) => self.step(count),
+            Command::Continue => self.resume(),
+            Command::Break(address) => {
+                if !self.vm.breakpoints_mut().add_breakpoint(address) {
+                    println!("There already is a breakpoint at {:04}", address);
+                }
+            }
+            Command::Delete(address) => {
+                if !self.vm.breakpoints_mut().remove_breakpoint(address) {
+                    println!("There is no breakpoint at {:04}", address);
+                }
+            }
+            Command::Watch(address, kind) => {
+                if !self.vm.breakpoints_mut().add_watchpoint(address, kind) {
+                    println!("There already is such a watchpoint");
+                }
+            }
+            Command::Unwatch(address, kind) => {
+                if !self.vm.breakpoints_mut().remove_watchpoint(address, kind) {
+                    println!("There is no such watchpoint");
+                }
+            }
+            Command::Inspect(start, count) => self.print_memory(start, count),
+            Command::Set(address, value) => {
+                if let Err(error) = self.vm.memory_mut().try_set(address, value) {
+                    println!("Error: {}", error);
+                }
+            }
+            Command::Input(inputs) => self.vm.extend_inputs(inputs),
+            Command::Outputs => {
+                println!("{:?}", self.vm.observer().outputs);
+                self.shown_outputs = self.vm.observer().outputs.len();
+            }
+            Command::List(context) => self.print_listing(context),
+            Command::Info => self.print_info(),
+            Command::Reset => {
+                let mut breakpoints = self.vm.breakpoints().clone();
+                breakpoints.rearm();
+                *self = Self::new(self.program.clone());
+                *self.vm.breakpoints_mut() = breakpoints;
+                self.print_listing(0);
+            }
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return false,
+        }
+
+        true
+    }
+
+    fn step(&mut self, count: usize) {
+        for _ in 0..count {
+            let line = Disassembler::decode_at(self.vm.memory(), self.vm.instruction_ptr());
+            match self.vm.step() {
+                Ok(Step::Executed(executed)) => {
+                    print!("  {}", line);
+                    if let Some(destination) = executed.destination {
+                        print!("    ; [{}] = {}", destination.address, destination.value);
+                    }
+                    println!();
+                    self.print_new_outputs();
+
+                    if executed.opcode == Opcode::Halt {
+                        println!("Halted");
+                        return;
+                    }
+                }
+                Ok(Step::WaitingForInput) => {
+                    println!("Waiting for input (use `input <value>...`)");
+                    return;
+                }
+                Err(error) => {
+                    println!("Error: {}", error);
+                    return;
+                }
+            }
+        }
+
+        self.print_listing(0);
+    }
+
+    fn resume(&mut self) {
+        loop {
+            let result = self.vm.run();
+            self.print_new_outputs();
+            match result {
+                Ok(VMResult::Output(_)) => continue,
+                Ok(VMResult::Halted) => println!("Halted"),
+                Ok(VMResult::WaitingForInput) => {
+                    println!("Waiting for input (use `input <value>...`)")
+                }
+                Ok(VMResult::Breakpoint { address, reason }) => {
+                    println!("{}", describe_break(address, reason))
+                }
+                Ok(VMResult::BudgetExhausted) => println!(
+                    "Paused after {} instructions (`continue` to keep going)",
+                    CONTINUE_BUDGET
+                ),
+                Err(error) => println!("Error: {}", error),
+            }
+
+            break;
+        }
+
+        self.print_listing(0);
+    }
+
+    fn print_new_outputs(&mut self) {
+        let outputs = &self.vm.observer().outputs;
+        for output in &outputs[self.shown_outputs..] {
+            println!("Output: {}", output);
+        }
+
+        self.shown_outputs = outputs.len();
+    }
+
+    fn print_memory(&self, start: usize, count: usize) {
+        let memory = self.vm.memory();
+        let end = start.saturating_add(count);
+        for line_start in (start..end).step_by(VALUES_PER_LINE) {
+            let values = (line_start..line_start.saturating_add(VALUES_PER_LINE).min(end))
+                .map(|address| memory.get(address).to_string())
+                .collect::<Vec<_>>();
+            println!("{:04}: {}", line_start, values.join(" "));
+        }
+    }
+
+    /// Prints the `context` lines before and after the instruction pointer
+    fn print_listing(&self, context: usize) {
+        let listing = self.listing(context);
+        if listing
+            .last()
+            .is_none_or(|line| line.address < self.vm.instruction_ptr())
+        {
+            // past the end of the memory, the VM reads zeros
+            println!(
+                "> {}",
+                Disassembler::decode_at(self.vm.memory(), self.vm.instruction_ptr())
+            );
+        }
+
+        for line in &listing {
+            self.print_line(line);
+        }
+    }
+
+    /// Returns the `context` lines before and after the instruction pointer
+    fn listing(&self, context: usize) -> Vec<Line<i64>> {
+        let memory = self.vm.memory();
+        let instruction_ptr = self.vm.instruction_ptr();
+
+        // instructions cannot be decoded backwards, so decoding starts far enough before
+        // the instruction pointer to give `context` lines, and resyncs on it:
+        // a line that would cover it only shows its first value
+        let mut before = VecDeque::new();
+        let mut address =
+            instruction_ptr.saturating_sub(context.saturating_mul(MAX_INSTRUCTION_WIDTH));
+        while address < instruction_ptr {
+            let Some(mut line) = Disassembler::starting_at(memory, address).next() else {
+                break;
+            };
+            if line.address + line.width() > instruction_ptr {
+                line.content = match line.content {
+                    LineContent::Reserve(_) => LineContent::Reserve(instruction_ptr - address),
+                    _ => LineContent::Data(*memory.get(address)),
+                };
+            }
+
+            address += line.width();
+            before.push_back(line);
+            if before.len() > context {
+                before.pop_front();
+            }
+        }
+
+        before
+            .into_iter()
+            .chain(
+                Disassembler::starting_at(memory, instruction_ptr).take(context.saturating_add(1)),
+            )
+            .collect()
+    }
+
+    fn print_line(&self, line: &Line<i64>) {
+        let marker = if line.address == self.vm.instruction_ptr() {
+            '>'
+        } else {
+            ' '
+        };
+        let breakpoint = if self
+            .vm
+            .breakpoints()
+            .breakpoints()
+            .any(|bp| bp == line.address)
+        {
+            '*'
+        } else {
+            ' '
+        };
+
+        println!("{}{}{}", marker, breakpoint, line);
+    }
+
+    fn print_info(&self) {
+        let mut breakpoints = self.vm.breakpoints().breakpoints().collect::<Vec<_>>();
+        breakpoints.sort_unstable();
+        let mut watchpoints = self.vm.breakpoints().watchpoints().collect::<Vec<_>>();
+        watchpoints.sort_unstable_by_key(|&(address, kind)| (address, kind as u8));
+
+        println!("Instruction pointer: {:04}", self.vm.instruction_ptr());
+        println!("Relative base: {}", self.vm.relative_base());
+        println!("Pending inputs: {}", self.vm.pending_inputs());
+        println!("Outputs: {}", self.vm.observer().outputs.len());
+        println!("Executed instructions: {}", self.vm.executed_instructions());
+        println!("Breakpoints: {:?}", breakpoints);
+        println!("Watchpoints: {:?}", watchpoints);
+    }
+}
+
+fn load_program(path: &str) -> Result<Memory<i64>, Box<dyn Error>> {
+    let source = fs::read_to_string(path)?;
+    if path.ends_with(".asm") {
+        Ok(asm::assemble(&source)?)
+    } else {
+        Ok(source.trim().parse()?)
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args().nth(1).ok_or("Usage: intcode-dbg <program>")?;
+    let mut debugger = Debugger::new(load_program(&path)?);
+    println!(
+        "Loaded {} value(s) from {}, type `help` for the list of commands",
+        debugger.program.len(),
+        path
+    );
+    debugger.print_listing(0);
+
+    let stdin = io::stdin();
+    let mut last_command = None;
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            // end of input
+            break;
+        }
+
+        let command = if line.trim().is_empty() {
+            match last_command.clone() {
+                Some(command) => command,
+                None => continue,
+            }
+        } else {
+            match line.parse::<Command>() {
+                Ok(command) => command,
+                Err(error) => {
+                    println!("{}", error);
+                    continue;
+                }
+            }
+        };
+
+        if !debugger.execute(command.clone()) {
+            break;
+        }
+
+        last_command = Some(command);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!("s".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 10".parse(), Ok(Command::Step(10)));
+        assert_eq!("  b   12 ".parse(), Ok(Command::Break(12)));
+        assert_eq!("w 5".parse(), Ok(Command::Watch(5, WatchKind::Write)));
+        assert_eq!(
+            "unwatch 5 change".parse(),
+            Ok(Command::Unwatch(5, WatchKind::Change))
+        );
+        assert_eq!("x 8".parse(), Ok(Command::Inspect(8, 2 * VALUES_PER_LINE)));
+        assert_eq!("set 3 -4".parse(), Ok(Command::Set(3, -4)));
+        assert_eq!("i 1 2 3".parse(), Ok(Command::Input(vec![1, 2, 3])));
+        assert_eq!("l".parse(), Ok(Command::List(5)));
+        assert_eq!("reset".parse(), Ok(Command::Reset));
+    }
+
+    #[test]
+    fn parse_invalid_commands() {
+        assert!("".parse::<Command>().is_err());
+        assert!("jump 3".parse::<Command>().is_err());
+        assert!("b".parse::<Command>().is_err());
+        assert!("b -1".parse::<Command>().is_err());
+        assert!("w 5 sometimes".parse::<Command>().is_err());
+        assert!("i".parse::<Command>().is_err());
+        assert!("set 3".parse::<Command>().is_err());
+        assert!("c now".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn reset_keeps_breakpoints_armed() {
+        let mut debugger = Debugger::new(Memory::from([104, 7, 99]));
+        debugger.execute(Command::Break(0));
+        debugger.execute(Command::Continue);
+        assert_eq!(debugger.vm.instruction_ptr(), 0);
+
+        debugger.execute(Command::Reset);
+        debugger.execute(Command::Continue);
+        assert_eq!(debugger.vm.instruction_ptr(), 0);
+        assert!(debugger.vm.observer().outputs.is_empty());
+
+        debugger.execute(Command::Continue);
+        assert_eq!(debugger.vm.observer().outputs, [7]);
+    }
+
+    #[test]
+    fn large_addresses() {
+        let mut debugger = Debugger::new(Memory::from([99]));
+        debugger.execute(Command::Set(1 << 40, 5));
+        assert_eq!(*debugger.vm.memory().get(1 << 40), 5);
+
+        debugger.execute(Command::Inspect(usize::MAX - 2, 16));
+
+        // reported instead of panicking
+        debugger.execute(Command::Set(usize::MAX, 0));
+        debugger.vm.memory_mut().set_limit(Some(100));
+        debugger.execute(Command::Set(100, 1));
+        assert_eq!(debugger.vm.memory().len(), (1 << 40) + 1);
+    }
+
+    #[test]
+    fn listing_around_instruction_pointer() {
+        // OUT #7, ADD #1, #2 -> [0], OUT #8, HLT
+        let mut debugger = Debugger::new(Memory::from([104, 7, 1101, 1, 2, 0, 104, 8, 99]));
+        debugger.execute(Command::Step(2));
+        let addresses = |debugger: &Debugger| {
+            debugger
+                .listing(1)
+                .iter()
+                .map(|line| line.address)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(addresses(&debugger), [2, 6, 8]);
+
+        // resyncs on the instruction pointer when a line would cover it
+        // JNZ #1, #4 jumps over the first value of ADD #99, #0 -> [0]
+        let mut debugger = Debugger::new(Memory::from([1105, 1, 4, 1101, 99, 0, 0, 0]));
+        debugger.execute(Command::Step(1));
+        let listing = debugger.listing(2);
+        assert_eq!(listing[1].address, 3);
+        assert_eq!(listing[1].content, LineContent::Data(1101));
+        assert_eq!(listing[2].address, 4);
+
+        // only decodes the stored pages before the instruction pointer
+        let mut debugger = Debugger::new(Memory::from([1105, 1, 1 << 40]));
+        debugger.execute(Command::Set(1 << 40, 99));
+        debugger.execute(Command::Step(1));
+        let listing = debugger.listing(2);
+        assert_eq!(listing.len(), 2);
+        assert_eq!(listing[0].content, LineContent::Reserve(8));
+        assert_eq!(listing[1].address, 1 << 40);
+    }
+}
@@ -0,0 +1,396 @@
+//! Breakpoints, watchpoints and conditional breakpoints of a [VM](crate::vm::IntcodeVM)
+//!
+//! When one of them is hit, [`vm.run()`](crate::vm::IntcodeVM::run) returns
+//! [`VMResult::Breakpoint`](crate::vm::VMResult::Breakpoint) and the VM can be resumed by calling it again.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{breakpoint::{BreakReason, WatchKind}, IntcodeVM, VMResult};
+//! let mut vm = IntcodeVM::new([1101, 1, 2, 9, 1101, 3, 4, 10, 99]);
+//! vm.breakpoints_mut().add_breakpoint(4);
+//! vm.breakpoints_mut().add_watchpoint(10, WatchKind::Write);
+//!
+//! // stops before executing the instruction at address 4
+//! assert_eq!(vm.run().unwrap(), VMResult::Breakpoint { address: 4, reason: BreakReason::Breakpoint });
+//!
+//! // stops after the instruction at address 4 wrote to address 10
+//! assert_eq!(
+//!     vm.run().unwrap(),
+//!     VMResult::Breakpoint {
+//!         address: 4,
+//!         reason: BreakReason::Watchpoint { address: 10, kind: WatchKind::Write },
+//!     }
+//! );
+//!
+//! assert_eq!(vm.run().unwrap(), VMResult::Halted);
+//! ```
+
+use std::collections::HashSet;
+
+use num::Integer;
+
+/// Why a [VM](crate::vm::IntcodeVM) stopped on a breakpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BreakReason {
+    /// The instruction at the reported address is about to be executed (it was not executed yet)
+    Breakpoint,
+    /// The instruction at the reported address accessed a watched memory address
+    /// (the instruction was executed)
+    Watchpoint { address: usize, kind: WatchKind },
+    /// The condition became true right before the instruction at the reported address
+    /// (it was not executed yet)
+    Condition(ConditionId),
+}
+
+/// The kind of memory access a watchpoint stops on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    /// The address is read by an instruction argument
+    Read,
+    /// The address is written to (even if the value does not change)
+    Write,
+    /// The value at the address is replaced by a different value
+    Change,
+}
+
+/// Identifier of a [Condition] added with [`Breakpoints::add_condition()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConditionId(usize);
+
+/// How a value is compared in a [Condition]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Returns `true` if `lhs` compares with `rhs` as described by this comparison
+    #[inline]
+    pub fn holds<T: Ord>(&self, lhs: &T, rhs: &T) -> bool {
+        match self {
+            Self::Equal => lhs == rhs,
+            Self::NotEqual => lhs != rhs,
+            Self::Less => lhs < rhs,
+            Self::LessOrEqual => lhs <= rhs,
+            Self::Greater => lhs > rhs,
+            Self::GreaterOrEqual => lhs >= rhs,
+        }
+    }
+}
+
+/// A condition checked before every instruction,
+/// the VM stops when it becomes true (it does not stop again until it became false in between)
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::{breakpoint::{BreakReason, Condition}, IntcodeVM, VMResult};
+/// // counts from 1 to 5 in address 12
+/// let mut vm = IntcodeVM::new([1001, 12, 1, 12, 1007, 12, 5, 13, 1005, 13, 0, 99, 0, 0]);
+/// let id = vm.breakpoints_mut().add_condition(Condition::memory_equals(12, 3));
+///
+/// assert_eq!(
+///     vm.run().unwrap(),
+///     VMResult::Breakpoint { address: 4, reason: BreakReason::Condition(id) }
+/// );
+/// assert_eq!(vm.memory()[12], 3);
+/// assert_eq!(vm.run().unwrap(), VMResult::Halted);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Condition<T> {
+    /// Compares the value at `address` with `value`
+    Memory {
+        address: usize,
+        comparison: Comparison,
+        value: T,
+    },
+    /// Compares the relative base with `value`
+    RelativeBase { comparison: Comparison, value: T },
+}
+
+impl<T> Condition<T>
+where
+    T: Integer,
+{
+    /// Creates a condition that is true when the value at `address` is `value`
+    #[inline]
+    pub fn memory_equals(address: usize, value: T) -> Self {
+        Self::Memory {
+            address,
+            comparison: Comparison::Equal,
+            value,
+        }
+    }
+
+    /// Creates a condition that is true when the relative base is greater than `value`
+    #[inline]
+    pub fn relative_base_greater_than(value: T) -> Self {
+        Self::RelativeBase {
+            comparison: Comparison::Greater,
+            value,
+        }
+    }
+
+    #[inline]
+    fn holds(&self, memory_value: impl FnOnce(usize) -> T, relative_base: &T) -> bool {
+        match self {
+            Self::Memory {
+                address,
+                comparison,
+                value,
+            } => comparison.holds(&memory_value(*address), value),
+            Self::RelativeBase { comparison, value } => comparison.holds(relative_base, value),
+        }
+    }
+}
+
+/// The breakpoints, watchpoints and conditions of a [VM](crate::vm::IntcodeVM)
+/// (see [`vm.breakpoints_mut()`](crate::vm::IntcodeVM::breakpoints_mut))
+#[derive(Debug, Clone)]
+pub struct Breakpoints<T> {
+    instructions: HashSet<usize>,
+    watchpoints: HashSet<(usize, WatchKind)>,
+    conditions: Vec<(ConditionId, Condition<T>, bool)>,
+    next_condition_id: usize,
+    /// Address of an instruction breakpoint that was just reported, so resuming does not stop on it again
+    resumed_from: Option<usize>,
+    /// Watchpoint hit by the instruction being executed
+    hit: Option<(usize, WatchKind)>,
+    /// Breakpoint to report on the next call to `run()` (the instruction that hit it also produced an output)
+    pending: Option<(usize, BreakReason)>,
+}
+
+impl<T> Default for Breakpoints<T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            instructions: HashSet::new(),
+            watchpoints: HashSet::new(),
+            conditions: Vec::new(),
+            next_condition_id: 0,
+            resumed_from: None,
+            hit: None,
+            pending: None,
+        }
+    }
+}
+
+impl<T> Breakpoints<T>
+where
+    T: Integer + Clone,
+{
+    /// Stops the VM before it executes the instruction at `address`
+    ///
+    /// Returns `false` if there already was a breakpoint at that address
+    #[inline]
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.instructions.insert(address)
+    }
+
+    /// Returns `false` if there was no breakpoint at that address
+    #[inline]
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.instructions.remove(&address)
+    }
+
+    /// Returns the addresses of the instruction breakpoints, in no particular order
+    #[inline]
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.instructions.iter().copied()
+    }
+
+    /// Stops the VM after it executes an instruction accessing `address` as described by `kind`
+    ///
+    /// Returns `false` if there already was such a watchpoint
+    #[inline]
+    pub fn add_watchpoint(&mut self, address: usize, kind: WatchKind) -> bool {
+        self.watchpoints.insert((address, kind))
+    }
+
+    /// Returns `false` if there was no such watchpoint
+    #[inline]
+    pub fn remove_watchpoint(&mut self, address: usize, kind: WatchKind) -> bool {
+        self.watchpoints.remove(&(address, kind))
+    }
+
+    /// Returns the watchpoints, in no particular order
+    #[inline]
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, WatchKind)> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    /// Stops the VM before an instruction when `condition` becomes true
+    #[inline]
+    pub fn add_condition(&mut self, condition: Condition<T>) -> ConditionId {
+        let id = ConditionId(self.next_condition_id);
+        self.next_condition_id += 1;
+        self.conditions.push((id, condition, false));
+        id
+    }
+
+    /// Returns `false` if there was no condition with that identifier
+    #[inline]
+    pub fn remove_condition(&mut self, id: ConditionId) -> bool {
+        let len = self.conditions.len();
+        self.conditions.retain(|(cond_id, _, _)| *cond_id != id);
+        len != self.conditions.len()
+    }
+
+    /// Returns the conditions with their identifiers, in the order they were added
+    #[inline]
+    pub fn conditions(&self) -> impl Iterator<Item = (ConditionId, &Condition<T>)> + '_ {
+        self.conditions
+            .iter()
+            .map(|(id, condition, _)| (*id, condition))
+    }
+
+    /// Removes every breakpoint, watchpoint and condition
+    #[inline]
+    pub fn clear(&mut self) {
+        self.instructions.clear();
+        self.watchpoints.clear();
+        self.conditions.clear();
+    }
+
+    /// Forgets which breakpoint was last hit, keeping every breakpoint, watchpoint and condition
+    ///
+    /// They then stop the VM as if they were just added. Use it before giving the breakpoints
+    /// of a VM to another one, otherwise it may resume past a breakpoint it never stopped on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{breakpoint::BreakReason, IntcodeVM, VMResult};
+    /// let program = [104, 1, 99];
+    /// let mut vm = IntcodeVM::new(program);
+    /// vm.breakpoints_mut().add_breakpoint(0);
+    /// assert_eq!(vm.run().unwrap(), VMResult::Breakpoint { address: 0, reason: BreakReason::Breakpoint });
+    ///
+    /// let mut breakpoints = vm.breakpoints().clone();
+    /// breakpoints.rearm();
+    /// let mut restarted = IntcodeVM::new(program);
+    /// *restarted.breakpoints_mut() = breakpoints;
+    /// assert_eq!(restarted.run().unwrap(), VMResult::Breakpoint { address: 0, reason: BreakReason::Breakpoint });
+    /// ```
+    #[inline]
+    pub fn rearm(&mut self) {
+        self.resumed_from = None;
+        self.hit = None;
+        self.pending = None;
+        for (_, _, was_true) in self.conditions.iter_mut() {
+            *was_true = false;
+        }
+    }
+
+    /// Returns `true` if the VM needs to check for breakpoints at all
+    #[inline]
+    pub(crate) fn is_active(&self) -> bool {
+        !self.instructions.is_empty()
+            || !self.watchpoints.is_empty()
+            || !self.conditions.is_empty()
+            || self.pending.is_some()
+    }
+
+    /// Checks the breakpoints to hit before executing the instruction at `address`
+    pub(crate) fn check_before(
+        &mut self,
+        address: usize,
+        memory_value: impl Fn(usize) -> T,
+        relative_base: &T,
+    ) -> Option<(usize, BreakReason)> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+
+        if self.resumed_from != Some(address) && self.instructions.contains(&address) {
+            self.resumed_from = Some(address);
+            return Some((address, BreakReason::Breakpoint));
+        }
+
+        let mut reason = None;
+        for (id, condition, was_true) in self.conditions.iter_mut() {
+            let is_true = condition.holds(&memory_value, relative_base);
+            if is_true && !*was_true && reason.is_none() {
+                reason = Some(BreakReason::Condition(*id));
+            }
+
+            *was_true = is_true;
+        }
+
+        reason.map(|reason| (address, reason))
+    }
+
+    /// Records an instruction reading `address`
+    #[inline]
+    pub(crate) fn on_read(&mut self, address: usize) {
+        if self.hit.is_none()
+            && !self.watchpoints.is_empty()
+            && self.watchpoints.contains(&(address, WatchKind::Read))
+        {
+            self.hit = Some((address, WatchKind::Read));
+        }
+    }
+
+    /// Records an instruction writing `new` at `address` (replacing `old`)
+    #[inline]
+    pub(crate) fn on_write(&mut self, address: usize, old: &T, new: &T) {
+        if self.hit.is_some() || self.watchpoints.is_empty() {
+            return;
+        }
+
+        if self.watchpoints.contains(&(address, WatchKind::Write)) {
+            self.hit = Some((address, WatchKind::Write));
+        } else if old != new && self.watchpoints.contains(&(address, WatchKind::Change)) {
+            self.hit = Some((address, WatchKind::Change));
+        }
+    }
+
+    /// Called once the instruction at `address` was executed,
+    /// returns the watchpoint it hit if any
+    ///
+    /// `produced_result` is `true` if the instruction makes `run()` return,
+    /// in which case the watchpoint is reported on the next call instead
+    #[inline]
+    pub(crate) fn after_execute(
+        &mut self,
+        address: usize,
+        produced_result: bool,
+    ) -> Option<(usize, BreakReason)> {
+        self.resumed_from = None;
+        let (watched, kind) = self.hit.take()?;
+        let hit = (
+            address,
+            BreakReason::Watchpoint {
+                address: watched,
+                kind,
+            },
+        );
+
+        if produced_result {
+            self.pending = Some(hit);
+            None
+        } else {
+            Some(hit)
+        }
+    }
+
+    /// Forgets the watchpoint hit by the instruction that was just executed or failed (if any)
+    #[inline]
+    pub(crate) fn discard_hit(&mut self) {
+        self.resumed_from = None;
+        self.hit = None;
+    }
+
+    /// Called once the halt instruction at `address` was executed: the VM stays on it,
+    /// so a breakpoint there is not hit again by the following calls to `run()`
+    #[inline]
+    pub(crate) fn on_halt(&mut self, address: usize) {
+        self.resumed_from = Some(address);
+    }
+}
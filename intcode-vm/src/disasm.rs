@@ -0,0 +1,243 @@
+//! Disassembler turning a [Memory] into a human readable listing
+//!
+//! Every instruction is printed on its own line as `address: MNEMONIC args -> destination`,
+//! the arguments being written with their mode:
+//!
+//! | Mode       | Syntax     |
+//! |------------|------------|
+//! | positional | `[100]`    |
+//! | immediate  | `#5`       |
+//! | relative   | `[rb+3]`   |
+//!
+//! Values that do not decode to an instruction are printed as `DATA value`,
+//! and the parts of a sparse memory that were never written to as `RESERVE count`
+//! (see [`MemoryBackend::next_stored_address()`]), so the listing of a
+//! [paged](Memory::into_paged) memory only covers the pages it stores.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{disasm, memory::Memory};
+//! let memory = Memory::from([21101, 3, 5, 100, 4, 100, 99, -7]);
+//!
+//! assert_eq!(
+//!     disasm::disassemble(&memory),
+//!     "\
+//! 0000: ADD #3, #5 -> [rb+100]
+//! 0004: OUT [100]
+//! 0006: HLT
+//! 0007: DATA -7
+//! "
+//! );
+//! ```
+
+use std::{fmt, marker::PhantomData};
+
+use num::{Integer, ToPrimitive};
+
+use crate::{
+    instruction::{ArgMode, Opcode},
+    memory::{Memory, MemoryBackend},
+    vm::instr,
+};
+
+/// What is stored at the address of a [Line]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LineContent<T> {
+    /// A valid instruction, with the mode and raw value of each of its arguments
+    Instruction {
+        opcode: Opcode,
+        args: Vec<(ArgMode, T)>,
+    },
+    /// A value that is not a valid instruction
+    Data(T),
+    /// Zeros that are not stored in the memory
+    Reserve(usize),
+}
+
+/// A single line of a listing, produced by a [Disassembler]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Line<T> {
+    /// Address of the first value of this line
+    pub address: usize,
+    /// Decoded value(s)
+    pub content: LineContent<T>,
+}
+
+impl<T> Line<T> {
+    /// Returns the number of values of memory this line covers
+    #[inline]
+    pub fn width(&self) -> usize {
+        match &self.content {
+            LineContent::Instruction { args, .. } => args.len() + 1,
+            LineContent::Data(_) => 1,
+            LineContent::Reserve(count) => *count,
+        }
+    }
+}
+
+impl<T> fmt::Display for Line<T>
+where
+    T: Integer + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}: ", self.address)?;
+        match &self.content {
+            LineContent::Data(value) => write!(f, "DATA {}", value),
+            LineContent::Reserve(count) => write!(f, "RESERVE {}", count),
+            LineContent::Instruction { opcode, args } => {
+                write!(f, "{}", opcode.mnemonic())?;
+                let destination = opcode.destination_arg().map(|arg_num| arg_num as usize - 1);
+                let mut separator = " ";
+                for (i, (mode, raw)) in args.iter().enumerate() {
+                    if Some(i) == destination {
+                        write!(f, " -> ")?;
+                    } else {
+                        write!(f, "{}", separator)?;
+                        separator = ", ";
+                    }
+
+                    write_arg(f, *mode, raw)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_arg<T>(f: &mut fmt::Formatter<'_>, mode: ArgMode, raw: &T) -> fmt::Result
+where
+    T: Integer + fmt::Display,
+{
+    match mode {
+        ArgMode::Positional => write!(f, "[{}]", raw),
+        ArgMode::Immediate => write!(f, "#{}", raw),
+        ArgMode::Relative if raw < &T::zero() => write!(f, "[rb{}]", raw),
+        ArgMode::Relative => write!(f, "[rb+{}]", raw),
+    }
+}
+
+/// [Iterator] over the [lines](Line) of the listing of a [Memory]
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::{disasm::{Disassembler, LineContent}, instruction::Opcode, memory::Memory};
+/// let memory = Memory::from([1, 0, 0, 3, 99]);
+/// let lines = Disassembler::new(&memory).collect::<Vec<_>>();
+///
+/// assert_eq!(lines.len(), 2);
+/// assert_eq!(lines[1].address, 4);
+/// assert!(matches!(lines[1].content, LineContent::Instruction { opcode: Opcode::Halt, .. }));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Disassembler<'m, T, M = Memory<T>>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    memory: &'m M,
+    address: usize,
+    _values: PhantomData<T>,
+}
+
+impl<'m, T, M> Disassembler<'m, T, M>
+where
+    T: Integer + Clone + ToPrimitive,
+    M: MemoryBackend<T>,
+{
+    /// Creates a disassembler over the whole `memory`
+    #[inline]
+    pub fn new(memory: &'m M) -> Self {
+        Self::starting_at(memory, 0)
+    }
+
+    /// Creates a disassembler over `memory` starting from `address`
+    #[inline]
+    pub fn starting_at(memory: &'m M, address: usize) -> Self {
+        Self {
+            memory,
+            address,
+            _values: PhantomData,
+        }
+    }
+
+    /// Decodes the line at `address`
+    ///
+    /// A value is only decoded as an instruction if all of its arguments are in the memory
+    /// and it has no mode digit besides those of its arguments,
+    /// so assembling the listing gives back the exact same values.
+    pub fn decode_at(memory: &M, address: usize) -> Line<T> {
+        let raw = memory.read(address);
+        let content = match instr::Instruction::from_address(memory, address) {
+            Ok(instruction)
+                if address.saturating_add(instruction.instruction_width()) <= memory.len()
+                    && Some(encode(&instruction)) == raw.to_u16() =>
+            {
+                LineContent::Instruction {
+                    opcode: instruction.opcode(),
+                    args: instruction
+                        .args()
+                        .into_iter()
+                        .map(|arg| (arg.mode(), arg.raw().clone()))
+                        .collect(),
+                }
+            }
+            _ => LineContent::Data(raw),
+        };
+
+        Line { address, content }
+    }
+}
+
+impl<T, M> Iterator for Disassembler<'_, T, M>
+where
+    T: Integer + Clone + ToPrimitive,
+    M: MemoryBackend<T>,
+{
+    type Item = Line<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stored = self.memory.next_stored_address(self.address)?;
+        if stored > self.address {
+            let line = Line {
+                address: self.address,
+                content: LineContent::Reserve(stored - self.address),
+            };
+            self.address = stored;
+            return Some(line);
+        }
+
+        let line = Self::decode_at(self.memory, self.address);
+        self.address += line.width();
+        Some(line)
+    }
+}
+
+/// Returns the value an instruction is represented by, using only the modes of its arguments
+fn encode<T>(instruction: &instr::Instruction<T>) -> u16
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    let modes = instruction
+        .args()
+        .into_iter()
+        .map(|arg| arg.mode())
+        .collect::<Vec<_>>();
+
+    instruction.opcode().encode(&modes)
+}
+
+/// Disassembles the whole `memory` into a listing (one [line](Line) per instruction,
+/// one per part of the memory that is not stored)
+pub fn disassemble<T, M>(memory: &M) -> String
+where
+    T: Integer + Clone + ToPrimitive + fmt::Display,
+    M: MemoryBackend<T>,
+{
+    Disassembler::new(memory).fold(String::new(), |mut listing, line| {
+        listing.push_str(&line.to_string());
+        listing.push('\n');
+        listing
+    })
+}
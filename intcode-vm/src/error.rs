@@ -1,9 +1,261 @@
+use std::fmt;
+
 use num::{Integer, ToPrimitive};
 use thiserror::Error;
 
+use crate::{
+    disasm::{Disassembler, Line, LineContent},
+    instruction::Opcode,
+    memory::Memory,
+    vm::StopReason,
+};
+
+/// Number of values before the instruction pointer in the window of an [ErrorContext]
+const WINDOW_BEFORE: usize = 4;
+/// Number of values from the instruction pointer in the window of an [ErrorContext]
+const WINDOW_AFTER: usize = 8;
+
 /// [Error](std::error::Error) type returned by the [VM](crate::vm::IntcodeVM)
+///
+/// Errors raised while running a program carry the [context](ErrorContext) they happened in.
+/// Formatting the error with `{:#}` adds a disassembly of the memory around the failing instruction.
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::{error::{VMError, VMErrorKind}, IntcodeVM};
+/// let mut vm = IntcodeVM::new([109, 5, 1, 0, 0, 0, 4, -1, 99]);
+///
+/// let error = vm.run().unwrap_err();
+/// assert!(matches!(error.kind, VMErrorKind::CannotCastToUsize(-1)));
+/// assert_eq!(error.arg_num, Some(1));
+/// assert_eq!(error.context.as_ref().unwrap().instruction_ptr, 6);
+/// assert_eq!(
+///     error.to_string(),
+///     "Could not cast -1 to usize (address is cast to usize before being used) \
+///      (instruction at address 6, argument n°1)"
+/// );
+/// assert_eq!(
+///     format!("{error:#}").lines().skip(1).collect::<Vec<_>>(),
+///     [
+///         "relative base: 5",
+///         "  0002: ADD [0], [0] -> [0]",
+///         "> 0006: OUT [-1]",
+///         "  0008: HLT",
+///         "  0009: DATA 0",
+///         "  0010: DATA 0",
+///         "  0011: DATA 0",
+///         "  0012: DATA 0",
+///         "  0013: DATA 0",
+///     ]
+/// );
+/// ```
+#[derive(Debug)]
+pub struct VMError<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    /// What went wrong
+    pub kind: VMErrorKind<T>,
+    /// Argument of the failing instruction the error is about (starting at 1), if any
+    pub arg_num: Option<u8>,
+    /// Where the VM was when the error happened,
+    /// [`None`] if the error did not come from running a program (e.g. restoring a snapshot)
+    pub context: Option<Box<ErrorContext<T>>>,
+}
+
+impl<T> VMError<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    /// Sets the argument the error is about
+    #[inline]
+    pub(crate) fn with_arg(mut self, arg_num: u8) -> Self {
+        self.arg_num = Some(arg_num);
+        self
+    }
+
+    /// Adds the context of the VM to the error, reading its memory with `read`,
+    /// unless it already has a context
+    #[cold]
+    pub(crate) fn with_context<F>(
+        mut self,
+        instruction_ptr: usize,
+        relative_base: T,
+        read: F,
+    ) -> Self
+    where
+        F: Fn(usize) -> T,
+    {
+        if self.context.is_some() {
+            return self;
+        }
+
+        let window_start = instruction_ptr.saturating_sub(WINDOW_BEFORE);
+        let window = (window_start..=instruction_ptr.saturating_add(WINDOW_AFTER - 1))
+            .map(read)
+            .collect::<Vec<_>>();
+        let opcode = window[instruction_ptr - window_start].clone();
+
+        self.arg_num = self.arg_num.or(match &self.kind {
+            VMErrorKind::InvalidArgMode { arg_num, .. }
+            | VMErrorKind::ArgModeCannotBeImmediate { arg_num, .. } => Some(*arg_num),
+            VMErrorKind::MemoryLimitExceeded { .. } | VMErrorKind::ReadOnlyAddress(_) => opcode
+                .to_u16()
+                .and_then(|opcode| Opcode::from_code(opcode % 100))
+                .and_then(|opcode| opcode.destination_arg()),
+            _ => None,
+        });
+        self.context = Some(Box::new(ErrorContext {
+            instruction_ptr,
+            relative_base,
+            opcode,
+            window_start,
+            window,
+        }));
+        self
+    }
+
+    /// Converts every value of the error with `f`
+    #[cfg(feature = "bigint")]
+    pub(crate) fn map<U, F>(self, mut f: F) -> VMError<U>
+    where
+        U: Integer + Clone + ToPrimitive,
+        F: FnMut(T) -> U,
+    {
+        VMError {
+            kind: self.kind.map(&mut f),
+            arg_num: self.arg_num,
+            context: self.context.map(|context| {
+                Box::new(ErrorContext {
+                    instruction_ptr: context.instruction_ptr,
+                    relative_base: f(context.relative_base),
+                    opcode: f(context.opcode),
+                    window_start: context.window_start,
+                    window: context.window.into_iter().map(f).collect(),
+                })
+            }),
+        }
+    }
+}
+
+impl<T> From<VMErrorKind<T>> for VMError<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    #[inline]
+    fn from(kind: VMErrorKind<T>) -> Self {
+        Self {
+            kind,
+            arg_num: None,
+            context: None,
+        }
+    }
+}
+
+impl<T> fmt::Display for VMError<T>
+where
+    T: Integer + Clone + ToPrimitive + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match (&self.context, self.arg_num) {
+            (Some(context), Some(arg_num)) => write!(
+                f,
+                " (instruction at address {}, argument n°{arg_num})",
+                context.instruction_ptr
+            )?,
+            (Some(context), None) => {
+                write!(f, " (instruction at address {})", context.instruction_ptr)?
+            }
+            (None, Some(arg_num)) => write!(f, " (argument n°{arg_num})")?,
+            (None, None) => {}
+        }
+
+        match &self.context {
+            Some(context) if f.alternate() => write!(f, "\n{context}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T> std::error::Error for VMError<T> where
+    T: Integer + Clone + ToPrimitive + fmt::Debug + fmt::Display
+{
+}
+
+/// Where the [VM](crate::vm::IntcodeVM) was when a [VMError] happened
+///
+/// Its [Display](fmt::Display) form is the relative base followed by a disassembly of
+/// [`window`](ErrorContext::window), the failing instruction being marked with `>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorContext<T> {
+    /// Address of the failing instruction
+    pub instruction_ptr: usize,
+    /// Value of the relative base
+    pub relative_base: T,
+    /// Value at the instruction pointer (the opcode and the modes of the arguments)
+    pub opcode: T,
+    /// Address of the first value of [`window`](ErrorContext::window)
+    pub window_start: usize,
+    /// Values of the memory around the instruction pointer
+    pub window: Vec<T>,
+}
+
+impl<T> ErrorContext<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    /// Returns the [lines](Line) of the disassembly of the window
+    ///
+    /// Values before the instruction pointer are only decoded as instructions
+    /// if they end before it, the line at the instruction pointer is always the failing instruction.
+    pub fn lines(&self) -> Vec<Line<T>> {
+        // usize::MAX cannot be stored, so the window stops before it
+        let end = self.window_start.saturating_add(self.window.len());
+        let window = self.window[..end - self.window_start].to_vec();
+        // paged, so only the pages around the window are allocated, wherever it is
+        let memory: Memory<T> = Memory::from_segments([(self.window_start, window)], true, None)
+            .unwrap_or_else(|_| unreachable!("the window fits in the address space"));
+
+        let mut lines = Vec::new();
+        let mut address = self.window_start;
+        while address < end {
+            let mut line = Disassembler::decode_at(&memory, address);
+            if address < self.instruction_ptr && address + line.width() > self.instruction_ptr {
+                line.content = LineContent::Data(memory.get(address).clone());
+            }
+
+            address += line.width();
+            lines.push(line);
+        }
+
+        lines
+    }
+}
+
+impl<T> fmt::Display for ErrorContext<T>
+where
+    T: Integer + Clone + ToPrimitive + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "relative base: {}", self.relative_base)?;
+        for line in self.lines() {
+            let marker = if line.address == self.instruction_ptr {
+                '>'
+            } else {
+                ' '
+            };
+            write!(f, "\n{marker} {line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What went wrong in a [VMError]
 #[derive(Error, Debug)]
-pub enum VMError<T>
+pub enum VMErrorKind<T>
 where
     T: Integer + Clone + ToPrimitive,
 {
@@ -27,6 +279,184 @@ where
         "The argument mode in opcode {opcode} for argument n°{arg_num} cannot be immediate (1)"
     )]
     ArgModeCannotBeImmediate { opcode: u16, arg_num: u8 },
+
+    #[error("The VM asked for an input but the input queue was empty ({} value(s) were output before that)", .outputs.len())]
+    MissingInput { outputs: Vec<T> },
+
+    #[error("The VM stopped before halting ({reason:?}, {} value(s) were output before that)", .outputs.len())]
+    Stopped { reason: StopReason, outputs: Vec<T> },
+
+    #[error("Cannot write to address {address}, the memory is limited to {limit} values")]
+    MemoryLimitExceeded { address: usize, limit: usize },
+
+    #[error("Cannot write to address {0}, it is read-only")]
+    ReadOnlyAddress(usize),
+
+    #[error("The snapshot has version {version} but only version {supported} can be restored")]
+    IncompatibleSnapshot { version: u32, supported: u32 },
+
+    #[error("The {opcode:?} instruction at address {ip} overflowed")]
+    ArithmeticOverflow { ip: usize, opcode: Opcode },
+}
+
+impl<T> VMErrorKind<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    /// Converts every value of the error with `f`
+    #[cfg(feature = "bigint")]
+    fn map<U, F>(self, mut f: F) -> VMErrorKind<U>
+    where
+        U: Integer + Clone + ToPrimitive,
+        F: FnMut(T) -> U,
+    {
+        match self {
+            Self::UnknownInstruction(opcode) => VMErrorKind::UnknownInstruction(opcode),
+            Self::CannotCastToU16(value) => VMErrorKind::CannotCastToU16(f(value)),
+            Self::CannotCastToUsize(value) => VMErrorKind::CannotCastToUsize(f(value)),
+            Self::InvalidArgMode {
+                opcode,
+                arg_num,
+                arg_mode,
+            } => VMErrorKind::InvalidArgMode {
+                opcode,
+                arg_num,
+                arg_mode,
+            },
+            Self::ArgModeCannotBeImmediate { opcode, arg_num } => {
+                VMErrorKind::ArgModeCannotBeImmediate { opcode, arg_num }
+            }
+            Self::MissingInput { outputs } => VMErrorKind::MissingInput {
+                outputs: outputs.into_iter().map(f).collect(),
+            },
+            Self::Stopped { reason, outputs } => VMErrorKind::Stopped {
+                reason,
+                outputs: outputs.into_iter().map(f).collect(),
+            },
+            Self::MemoryLimitExceeded { address, limit } => {
+                VMErrorKind::MemoryLimitExceeded { address, limit }
+            }
+            Self::ReadOnlyAddress(address) => VMErrorKind::ReadOnlyAddress(address),
+            Self::IncompatibleSnapshot { version, supported } => {
+                VMErrorKind::IncompatibleSnapshot { version, supported }
+            }
+            Self::ArithmeticOverflow { ip, opcode } => {
+                VMErrorKind::ArithmeticOverflow { ip, opcode }
+            }
+        }
+    }
 }
 
 pub type Result<T, I> = std::result::Result<T, VMError<I>>;
+
+/// [Error](std::error::Error) type returned by the [assembler](crate::asm)
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("line {line}, column {column}: {kind}")]
+pub struct AsmError {
+    /// Line of the error (starting at 1)
+    pub line: usize,
+    /// Column of the error (starting at 1)
+    pub column: usize,
+    /// What went wrong
+    pub kind: AsmErrorKind,
+}
+
+/// What went wrong while assembling a program (see [AsmError])
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    #[error("Unknown mnemonic `{0}`")]
+    UnknownMnemonic(String),
+
+    #[error("`{mnemonic}` expects {expected} argument(s), got {found}")]
+    WrongArgCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("`{0}` does not write to memory, it cannot have a destination (`->`)")]
+    UnexpectedDestination(String),
+
+    #[error("`{0}` is not a valid argument (expected `#value`, `[address]` or `[rb+offset]`)")]
+    InvalidArgument(String),
+
+    #[error("`{0}` is not a valid value (expected a number or a label, optionally followed by `+number` or `-number`)")]
+    InvalidValue(String),
+
+    #[error("`{0}` is not a valid label name")]
+    InvalidLabel(String),
+
+    #[error("The label `{0}` is defined more than once")]
+    DuplicateLabel(String),
+
+    #[error("The label `{0}` is not defined")]
+    UndefinedLabel(String),
+
+    #[error("`{0}` cannot be represented by the type of the values of the memory")]
+    ValueOutOfRange(String),
+
+    #[error("This line is annotated with address {annotated} but is at address {actual}")]
+    AddressMismatch { annotated: usize, actual: usize },
+
+    #[error("The program does not fit in {0} values")]
+    ProgramTooLarge(usize),
+}
+
+/// [Error](std::error::Error) type returned by a [Pipeline](crate::pipeline::Pipeline)
+///
+/// `stage` is the index of the VM that misbehaved in the pipeline (starting at 0).
+#[derive(Error, Debug)]
+pub enum PipelineError<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    #[error("Stage {stage} failed: {source}")]
+    Stage {
+        stage: usize,
+        #[source]
+        source: VMError<T>,
+    },
+
+    #[error("Stage {stage} stopped before halting ({reason:?})")]
+    Stopped { stage: usize, reason: StopReason },
+
+    #[error("Stage {stage} is waiting for an input that no stage will ever output")]
+    Stalled { stage: usize },
+
+    #[error("The pipeline halted but its last stage did not output anything")]
+    NoOutput,
+
+    #[error("The pipeline does not have any stage")]
+    Empty,
+}
+
+/// [Error](std::error::Error) type returned by a [Network](crate::network::Network)
+///
+/// `machine` is the address of the machine that misbehaved.
+#[derive(Error, Debug)]
+pub enum NetworkError<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    #[error("Machine {machine} failed: {source}")]
+    Machine {
+        machine: usize,
+        #[source]
+        source: VMError<T>,
+    },
+
+    #[error("Machine {machine} stopped before halting ({reason:?})")]
+    Stopped { machine: usize, reason: StopReason },
+
+    #[error("Machine {machine} sent a packet to {address}, which is not a valid address")]
+    InvalidAddress { machine: usize, address: T },
+
+    #[error("The packet handler sent a packet to {0}, which is not the address of a machine")]
+    UnknownDestination(usize),
+
+    #[error("The network is idle and the packet handler did not send any packet")]
+    Idle,
+
+    #[error("Every machine of the network halted")]
+    Halted,
+}
@@ -0,0 +1,463 @@
+//! A [VM](FastIntcodeVM) specialized for `i64` values
+//!
+//! [FastIntcodeVM] executes programs exactly like [`IntcodeVM<i64>`](crate::vm::IntcodeVM)
+//! (same results, same errors), but without any of its debugging features
+//! (observer, breakpoints, journal, total budget, snapshots, memory limit or custom memory backend),
+//! which lets it decode and execute instructions much faster.
+//! It handles overflows with the same [policies](OverflowPolicy).
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{FastIntcodeVM, VMResult};
+//! // outputs its input multiplied by 3
+//! let mut vm = FastIntcodeVM::new([3, 9, 102, 3, 9, 9, 4, 9, 99, 0]);
+//! vm.push_input(14);
+//!
+//! assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+//! assert_eq!(vm.run().unwrap(), VMResult::Halted);
+//! ```
+
+use std::str::FromStr;
+
+use crate::{
+    arithmetic::{OverflowArithmetic, OverflowPolicy},
+    error::{self, VMError, VMErrorKind},
+    instruction::Opcode,
+    io::InputQueue,
+    memory::Memory,
+    vm::{StopReason, VMResult},
+};
+
+/// Opcode with its 3 argument modes, as found in memory
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+    opcode: u16,
+    modes: [u8; 3],
+}
+
+/// A VM running programs with `i64` values, faster than [`IntcodeVM<i64>`](crate::vm::IntcodeVM)
+///
+/// See the [module documentation](crate::fast) for what it does not support.
+#[derive(Debug, Clone, Default)]
+pub struct FastIntcodeVM {
+    memory: Vec<i64>,
+    instruction_ptr: usize,
+    relative_base: i64,
+    inputs: InputQueue<i64>,
+    overflow_policy: OverflowPolicy,
+    run_budget: Option<u64>,
+}
+
+impl FastIntcodeVM {
+    /// Creates a new VM from the given [`memory`](Memory)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::FastIntcodeVM;
+    /// let vm = FastIntcodeVM::new([1, 0, 0, 3, 99]);
+    /// ```
+    #[inline]
+    pub fn new<I: Into<Memory<i64>>>(memory: I) -> Self {
+        Self {
+            memory: memory.into().iter().copied().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Runs the VM until it encounters an output, halts or needs an input
+    ///
+    /// Same as [`IntcodeVM::run()`](crate::vm::IntcodeVM::run), except it never returns
+    /// [`VMResult::Breakpoint`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{FastIntcodeVM, VMResult};
+    /// let mut vm = FastIntcodeVM::new([104, 7, 3, 0, 99]);
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(7));
+    /// assert_eq!(vm.run().unwrap(), VMResult::WaitingForInput);
+    /// vm.push_input(1);
+    /// assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    /// ```
+    pub fn run(&mut self) -> error::Result<VMResult<i64>, i64> {
+        self.run_counted(&mut 0)
+    }
+
+    /// Same as [`vm.run()`](FastIntcodeVM::run), `executed` is the number of instructions
+    /// already executed by the run, to enforce the run budget
+    #[inline]
+    fn run_counted(&mut self, executed: &mut u64) -> error::Result<VMResult<i64>, i64> {
+        let result = self.run_inner(executed);
+        result.map_err(|error| self.with_context(error))
+    }
+
+    /// Same as [`vm.run_counted()`](FastIntcodeVM::run_counted), without adding context to the errors
+    #[inline]
+    fn run_inner(&mut self, executed: &mut u64) -> error::Result<VMResult<i64>, i64> {
+        let budget = self.run_budget.unwrap_or(u64::MAX);
+        loop {
+            let Decoded { opcode, modes } = self.decode()?;
+            if *executed >= budget
+                && opcode % 100 != 99
+                && Opcode::from_code(opcode % 100).is_some()
+            {
+                return Ok(VMResult::BudgetExhausted);
+            }
+            // a read waiting for an input or an error ends the run, so it is never uncounted
+            *executed += 1;
+
+            match opcode % 100 {
+                1 => {
+                    let (lhs, rhs) = (self.operand(modes, 1)?, self.operand(modes, 2)?);
+                    // resolved before the operation, so errors come in the same order as IntcodeVM
+                    let destination = self.destination(opcode, modes, 3)?;
+                    let result = self
+                        .overflow_checked(Opcode::Add, lhs.add_with(&rhs, self.overflow_policy))?;
+                    self.write(destination, result);
+                    self.instruction_ptr += 4;
+                }
+                2 => {
+                    let (lhs, rhs) = (self.operand(modes, 1)?, self.operand(modes, 2)?);
+                    // resolved before the operation, so errors come in the same order as IntcodeVM
+                    let destination = self.destination(opcode, modes, 3)?;
+                    let result = self
+                        .overflow_checked(Opcode::Mul, lhs.mul_with(&rhs, self.overflow_policy))?;
+                    self.write(destination, result);
+                    self.instruction_ptr += 4;
+                }
+                3 => {
+                    let destination = self.destination(opcode, modes, 1)?;
+                    let Some(input) = self.inputs.pop_front() else {
+                        return Ok(VMResult::WaitingForInput);
+                    };
+                    self.write(destination, input);
+                    self.instruction_ptr += 2;
+                }
+                4 => {
+                    let output = self.operand(modes, 1)?;
+                    self.instruction_ptr += 2;
+                    return Ok(VMResult::Output(output));
+                }
+                5 | 6 => {
+                    if (self.operand(modes, 1)? != 0) == (opcode % 100 == 5) {
+                        self.instruction_ptr = Self::address(self.operand(modes, 2)?, 2)?;
+                    } else {
+                        self.instruction_ptr += 3;
+                    }
+                }
+                7 => {
+                    let result = self.operand(modes, 1)? < self.operand(modes, 2)?;
+                    let destination = self.destination(opcode, modes, 3)?;
+                    self.write(destination, result.into());
+                    self.instruction_ptr += 4;
+                }
+                8 => {
+                    let result = self.operand(modes, 1)? == self.operand(modes, 2)?;
+                    let destination = self.destination(opcode, modes, 3)?;
+                    self.write(destination, result.into());
+                    self.instruction_ptr += 4;
+                }
+                9 => {
+                    self.relative_base = self.overflow_checked(
+                        Opcode::AddRelativeBase,
+                        self.relative_base
+                            .add_with(&self.operand(modes, 1)?, self.overflow_policy),
+                    )?;
+                    self.instruction_ptr += 2;
+                }
+                99 => return Ok(VMResult::Halted),
+                unknown => return Err(VMErrorKind::UnknownInstruction(unknown).into()),
+            }
+        }
+    }
+
+    /// Runs the VM until it halts, collecting every value it outputs along the way
+    ///
+    /// Same as [`IntcodeVM::run_until_halt()`](crate::vm::IntcodeVM::run_until_halt).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::FastIntcodeVM;
+    /// // outputs its input twice
+    /// let mut vm = FastIntcodeVM::new([3, 0, 4, 0, 4, 0, 99]);
+    /// vm.push_input(7);
+    ///
+    /// assert_eq!(vm.run_until_halt().unwrap(), vec![7, 7]);
+    /// ```
+    pub fn run_until_halt(&mut self) -> error::Result<Vec<i64>, i64> {
+        match self.run_until_input()? {
+            (outputs, StopReason::Halted) => Ok(outputs),
+            (outputs, StopReason::WaitingForInput) => {
+                Err(self.with_context(VMErrorKind::MissingInput { outputs }.into()))
+            }
+            (outputs, reason) => {
+                Err(self.with_context(VMErrorKind::Stopped { reason, outputs }.into()))
+            }
+        }
+    }
+
+    /// Runs the VM until it halts or asks for an input while the input queue is empty,
+    /// collecting every value it outputs along the way
+    ///
+    /// Same as [`IntcodeVM::run_until_input()`](crate::vm::IntcodeVM::run_until_input).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{FastIntcodeVM, StopReason};
+    /// // outputs its input twice, forever
+    /// let mut vm = FastIntcodeVM::new([3, 9, 4, 9, 4, 9, 1105, 1, 0]);
+    ///
+    /// vm.extend_inputs([1, 2]);
+    /// assert_eq!(vm.run_until_input().unwrap(), (vec![1, 1, 2, 2], StopReason::WaitingForInput));
+    /// ```
+    pub fn run_until_input(&mut self) -> error::Result<(Vec<i64>, StopReason), i64> {
+        let mut outputs = Vec::new();
+        let mut executed = 0;
+        loop {
+            match self.run_counted(&mut executed)? {
+                VMResult::Output(value) => outputs.push(value),
+                VMResult::Halted => return Ok((outputs, StopReason::Halted)),
+                VMResult::WaitingForInput => return Ok((outputs, StopReason::WaitingForInput)),
+                VMResult::Breakpoint { address, reason } => {
+                    return Ok((outputs, StopReason::Breakpoint { address, reason }))
+                }
+                VMResult::BudgetExhausted => return Ok((outputs, StopReason::BudgetExhausted)),
+            }
+        }
+    }
+
+    /// Adds `input` at the end of the input queue
+    #[inline]
+    pub fn push_input(&mut self, input: i64) {
+        self.inputs.push_back(input);
+    }
+
+    /// Adds every value of `inputs` at the end of the input queue, in order
+    #[inline]
+    pub fn extend_inputs<I: IntoIterator<Item = i64>>(&mut self, inputs: I) {
+        self.inputs.extend(inputs);
+    }
+
+    /// Returns the next value the VM will read, if any
+    #[inline]
+    pub const fn get_next_input(&self) -> &Option<i64> {
+        self.inputs.next()
+    }
+
+    /// Same as [`vm.get_next_input()`](FastIntcodeVM::get_next_input), as an [`Option`] of a reference
+    #[inline]
+    pub fn peek_input(&self) -> Option<&i64> {
+        self.inputs.next().as_ref()
+    }
+
+    /// Replaces every pending input with `next_input`,
+    /// returning the input that would have been consumed next
+    #[inline]
+    pub fn set_next_input(&mut self, next_input: i64) -> Option<i64> {
+        let previous = self.inputs.pop_front();
+        self.inputs.clear();
+        self.inputs.push_back(next_input);
+        previous
+    }
+
+    /// Returns the number of values in the input queue
+    #[inline]
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Sets what the VM does when an `ADD`, a `MUL` or an `ARB` instruction overflows
+    ///
+    /// Same as [`IntcodeVM::set_overflow_policy()`](crate::vm::IntcodeVM::set_overflow_policy).
+    #[inline]
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// Returns what the VM does when an arithmetic instruction overflows
+    #[inline]
+    pub const fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Limits the number of instructions a single call to [`vm.run()`](FastIntcodeVM::run)
+    /// (or [`vm.run_until_input()`](FastIntcodeVM::run_until_input)...) can execute,
+    /// [`None`] to remove the limit
+    ///
+    /// Same as [`IntcodeVM::set_run_budget()`](crate::vm::IntcodeVM::set_run_budget).
+    #[inline]
+    pub fn set_run_budget(&mut self, budget: Option<u64>) {
+        self.run_budget = budget;
+    }
+
+    /// Returns the address of the next instruction to be executed
+    #[inline]
+    pub const fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    /// Returns the value of the relative base
+    #[inline]
+    pub const fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// Returns the values stored in the memory of the VM, from address 0
+    /// (every address after them is 0)
+    #[inline]
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+
+    /// Returns a mutable reference to the values stored in the memory of the VM
+    #[inline]
+    pub fn memory_mut(&mut self) -> &mut [i64] {
+        &mut self.memory
+    }
+
+    /// Returns the memory of the VM
+    #[inline]
+    pub fn into_memory(self) -> Memory<i64> {
+        self.memory.into()
+    }
+
+    /// Reads the opcode and argument modes of the instruction at the instruction pointer
+    #[inline]
+    fn decode(&self) -> error::Result<Decoded, i64> {
+        let value = self.read(self.instruction_ptr);
+        let opcode = u16::try_from(value).map_err(|_| VMErrorKind::CannotCastToU16(value))?;
+
+        let modes = [
+            (opcode / 100 % 10) as u8,
+            (opcode / 1000 % 10) as u8,
+            (opcode / 10000) as u8,
+        ];
+        if let Some(arg_num) = modes.iter().position(|&mode| mode > 2) {
+            return Err(VMError::from(VMErrorKind::InvalidArgMode {
+                opcode,
+                arg_num: arg_num as u8 + 1,
+                arg_mode: modes[arg_num],
+            })
+            .with_arg(arg_num as u8 + 1));
+        }
+
+        Ok(Decoded { opcode, modes })
+    }
+
+    /// Returns the value of the argument n°`arg_num` of the current instruction
+    #[inline]
+    fn operand(&self, modes: [u8; 3], arg_num: usize) -> error::Result<i64, i64> {
+        let raw = self.read(self.instruction_ptr + arg_num);
+        Ok(match modes[arg_num - 1] {
+            0 => self.read(Self::address(raw, arg_num)?),
+            1 => raw,
+            _ => self.read(self.relative_address(raw, arg_num)?),
+        })
+    }
+
+    /// Returns the address the argument n°`arg_num` of the current instruction writes to
+    #[inline]
+    fn destination(
+        &self,
+        opcode: u16,
+        modes: [u8; 3],
+        arg_num: usize,
+    ) -> error::Result<usize, i64> {
+        let raw = self.read(self.instruction_ptr + arg_num);
+        match modes[arg_num - 1] {
+            0 => Self::address(raw, arg_num),
+            1 => Err(VMError::from(VMErrorKind::ArgModeCannotBeImmediate {
+                opcode,
+                arg_num: arg_num as u8,
+            })
+            .with_arg(arg_num as u8)),
+            _ => self.relative_address(raw, arg_num),
+        }
+    }
+
+    /// Returns the result of an arithmetic operation of the current instruction,
+    /// fails if it overflowed
+    #[inline]
+    fn overflow_checked(&self, opcode: Opcode, result: Option<i64>) -> error::Result<i64, i64> {
+        result.ok_or_else(|| {
+            VMErrorKind::ArithmeticOverflow {
+                ip: self.instruction_ptr,
+                opcode,
+            }
+            .into()
+        })
+    }
+
+    /// Adds the current state of the VM to `error`
+    #[cold]
+    fn with_context(&self, error: VMError<i64>) -> VMError<i64> {
+        error.with_context(self.instruction_ptr, self.relative_base, |address| {
+            self.read(address)
+        })
+    }
+
+    /// Casts `value`, the address of the argument n°`arg_num` of the current instruction
+    #[inline]
+    fn address(value: i64, arg_num: usize) -> error::Result<usize, i64> {
+        usize::try_from(value).map_err(|_| {
+            VMError::from(VMErrorKind::CannotCastToUsize(value)).with_arg(arg_num as u8)
+        })
+    }
+
+    /// Returns the address `raw`, the argument n°`arg_num` of the current instruction, points to
+    /// relatively to the relative base
+    ///
+    /// An address that overflows is an error whatever the [OverflowPolicy].
+    #[inline]
+    fn relative_address(&self, raw: i64, arg_num: usize) -> error::Result<usize, i64> {
+        let address = raw.checked_add(self.relative_base).ok_or_else(|| {
+            let opcode = (self.read(self.instruction_ptr) % 100) as u16;
+            VMError::from(VMErrorKind::ArithmeticOverflow {
+                ip: self.instruction_ptr,
+                opcode: Opcode::from_code(opcode).expect("the instruction was decoded"),
+            })
+            .with_arg(arg_num as u8)
+        })?;
+        Self::address(address, arg_num)
+    }
+
+    #[inline]
+    fn read(&self, address: usize) -> i64 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    #[inline]
+    fn write(&mut self, address: usize, value: i64) {
+        if let Some(cell) = self.memory.get_mut(address) {
+            *cell = value;
+        } else {
+            self.memory.resize(address, 0);
+            self.memory.push(value);
+        }
+    }
+}
+
+impl<I> From<I> for FastIntcodeVM
+where
+    I: Into<Memory<i64>>,
+{
+    #[inline]
+    fn from(memory: I) -> Self {
+        Self::new(memory)
+    }
+}
+
+impl FromStr for FastIntcodeVM {
+    type Err = <i64 as FromStr>::Err;
+
+    /// Parses a comma separated list of values (see [`Memory::from_str()`])
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s.parse::<Memory<i64>>()?))
+    }
+}
@@ -0,0 +1,257 @@
+//! Public description of the instructions understood by the [VM](crate::vm::IntcodeVM)
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The operation of an instruction (the last 2 digits of its first value)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Opcode {
+    /// `01`: adds its first 2 arguments together and stores the result into the third
+    Add,
+    /// `02`: multiplies its first 2 arguments together and stores the result into the third
+    Mul,
+    /// `03`: stores an input value into its argument
+    ReadInput,
+    /// `04`: outputs its argument
+    WriteOutput,
+    /// `05`: jumps to its second argument if its first argument is not zero
+    JmpIfTrue,
+    /// `06`: jumps to its second argument if its first argument is zero
+    JmpIfFalse,
+    /// `07`: stores 1 into its third argument if the first is less than the second, 0 otherwise
+    LessThan,
+    /// `08`: stores 1 into its third argument if the first 2 are equal, 0 otherwise
+    Equals,
+    /// `09`: adds its argument to the relative base
+    AddRelativeBase,
+    /// `99`: stops the program
+    Halt,
+}
+
+impl Opcode {
+    /// Returns the opcode corresponding to `code` (without the argument modes), if any
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::instruction::Opcode;
+    /// assert_eq!(Opcode::from_code(2), Some(Opcode::Mul));
+    /// assert_eq!(Opcode::from_code(99), Some(Opcode::Halt));
+    /// assert_eq!(Opcode::from_code(10), None);
+    /// ```
+    #[inline]
+    pub const fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            1 => Self::Add,
+            2 => Self::Mul,
+            3 => Self::ReadInput,
+            4 => Self::WriteOutput,
+            5 => Self::JmpIfTrue,
+            6 => Self::JmpIfFalse,
+            7 => Self::LessThan,
+            8 => Self::Equals,
+            9 => Self::AddRelativeBase,
+            99 => Self::Halt,
+            _ => return None,
+        })
+    }
+
+    /// Returns the code of this opcode (without any argument mode)
+    #[inline]
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::Add => 1,
+            Self::Mul => 2,
+            Self::ReadInput => 3,
+            Self::WriteOutput => 4,
+            Self::JmpIfTrue => 5,
+            Self::JmpIfFalse => 6,
+            Self::LessThan => 7,
+            Self::Equals => 8,
+            Self::AddRelativeBase => 9,
+            Self::Halt => 99,
+        }
+    }
+
+    /// Returns the short name of this opcode, as used by the [disassembler](crate::disasm)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::instruction::Opcode;
+    /// assert_eq!(Opcode::Add.mnemonic(), "ADD");
+    /// assert_eq!(Opcode::JmpIfTrue.mnemonic(), "JNZ");
+    /// ```
+    #[inline]
+    pub const fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Add => "ADD",
+            Self::Mul => "MUL",
+            Self::ReadInput => "IN",
+            Self::WriteOutput => "OUT",
+            Self::JmpIfTrue => "JNZ",
+            Self::JmpIfFalse => "JZ",
+            Self::LessThan => "LT",
+            Self::Equals => "EQ",
+            Self::AddRelativeBase => "ARB",
+            Self::Halt => "HLT",
+        }
+    }
+
+    /// Returns the opcode whose [mnemonic](Opcode::mnemonic) is `mnemonic` (ignoring case), if any
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::instruction::Opcode;
+    /// assert_eq!(Opcode::from_mnemonic("add"), Some(Opcode::Add));
+    /// assert_eq!(Opcode::from_mnemonic("HLT"), Some(Opcode::Halt));
+    /// assert_eq!(Opcode::from_mnemonic("NOP"), None);
+    /// ```
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        [
+            Self::Add,
+            Self::Mul,
+            Self::ReadInput,
+            Self::WriteOutput,
+            Self::JmpIfTrue,
+            Self::JmpIfFalse,
+            Self::LessThan,
+            Self::Equals,
+            Self::AddRelativeBase,
+            Self::Halt,
+        ]
+        .into_iter()
+        .find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
+
+    /// Returns the value representing this opcode with the given argument modes (in order)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::instruction::{ArgMode, Opcode};
+    /// assert_eq!(Opcode::Mul.encode(&[ArgMode::Positional, ArgMode::Immediate, ArgMode::Relative]), 21002);
+    /// assert_eq!(Opcode::Halt.encode(&[]), 99);
+    /// ```
+    #[inline]
+    pub fn encode(&self, modes: &[ArgMode]) -> u16 {
+        modes
+            .iter()
+            .zip([100, 1000, 10000])
+            .fold(self.code(), |op, (mode, factor)| {
+                op + mode.code() as u16 * factor
+            })
+    }
+
+    /// Returns the number of arguments following this opcode in memory
+    #[inline]
+    pub const fn arg_count(&self) -> usize {
+        match self {
+            Self::Add | Self::Mul | Self::LessThan | Self::Equals => 3,
+            Self::JmpIfTrue | Self::JmpIfFalse => 2,
+            Self::ReadInput | Self::WriteOutput | Self::AddRelativeBase => 1,
+            Self::Halt => 0,
+        }
+    }
+
+    /// Returns the number (starting at 1) of the argument this opcode writes to, if any
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::instruction::Opcode;
+    /// assert_eq!(Opcode::Add.destination_arg(), Some(3));
+    /// assert_eq!(Opcode::ReadInput.destination_arg(), Some(1));
+    /// assert_eq!(Opcode::WriteOutput.destination_arg(), None);
+    /// ```
+    #[inline]
+    pub const fn destination_arg(&self) -> Option<u8> {
+        match self {
+            Self::Add | Self::Mul | Self::LessThan | Self::Equals => Some(3),
+            Self::ReadInput => Some(1),
+            Self::WriteOutput
+            | Self::JmpIfTrue
+            | Self::JmpIfFalse
+            | Self::AddRelativeBase
+            | Self::Halt => None,
+        }
+    }
+}
+
+/// How the raw value of an argument is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArgMode {
+    /// `0`: the argument is the address of the value
+    Positional,
+    /// `1`: the argument is the value itself
+    Immediate,
+    /// `2`: the argument is the address of the value, relative to the relative base
+    Relative,
+}
+
+impl ArgMode {
+    /// Returns the digit representing this mode in an opcode
+    #[inline]
+    pub const fn code(&self) -> u8 {
+        match self {
+            Self::Positional => 0,
+            Self::Immediate => 1,
+            Self::Relative => 2,
+        }
+    }
+}
+
+/// An argument read by an [ExecutedInstruction]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Operand<T> {
+    /// Mode of the argument
+    pub mode: ArgMode,
+    /// Value of the argument as it was in memory
+    pub raw: T,
+    /// Value the argument resolved to
+    ///
+    /// [`None`] if it points to an address that cannot exist and the instruction
+    /// did not need it (the target of a jump that was not taken)
+    pub value: Option<T>,
+}
+
+/// The argument an [ExecutedInstruction] wrote to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Destination<T> {
+    /// Mode of the argument
+    pub mode: ArgMode,
+    /// Value of the argument as it was in memory
+    pub raw: T,
+    /// Address that was written to
+    pub address: usize,
+    /// Value that was written
+    pub value: T,
+}
+
+/// Description of an instruction executed by [`vm.step()`](crate::vm::IntcodeVM::step)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExecutedInstruction<T> {
+    /// Address of the instruction
+    pub address: usize,
+    /// Operation of the instruction
+    pub opcode: Opcode,
+    /// Arguments read by the instruction, in order (the destination is not included)
+    pub operands: Vec<Operand<T>>,
+    /// Argument written by the instruction, if any
+    pub destination: Option<Destination<T>>,
+    /// Value of the instruction pointer after the instruction was executed
+    pub next_instruction_ptr: usize,
+}
+
+/// Result of [`vm.step()`](crate::vm::IntcodeVM::step)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Step<T> {
+    /// An instruction was executed
+    ///
+    /// Halt instructions are also reported here (and do not move the instruction pointer)
+    Executed(ExecutedInstruction<T>),
+    /// The instruction is a read instruction and the input queue is empty, nothing was executed
+    WaitingForInput,
+}
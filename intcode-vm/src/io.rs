@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+
+/// Provides input values to a [VM](crate::vm::IntcodeVM)
+/// (see [`vm.run_with()`](crate::vm::IntcodeVM::run_with))
+///
+/// Implemented for [`VecDeque`], closures returning an [`Option<T>`]
+/// and iterators (through [`InputIter`])
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::io::InputSource;
+/// let mut counter = 0;
+/// let mut source = || {
+///     counter += 1;
+///     Some(counter)
+/// };
+///
+/// assert_eq!(source.next_input(), Some(1));
+/// assert_eq!(source.next_input(), Some(2));
+/// ```
+pub trait InputSource<T> {
+    /// Returns the next input value, or [`None`] if no value is available (yet)
+    ///
+    /// When this returns [`None`], the VM stops with [`StopReason::WaitingForInput`](crate::vm::StopReason::WaitingForInput)
+    fn next_input(&mut self) -> Option<T>;
+}
+
+/// Receives the values output by a [VM](crate::vm::IntcodeVM)
+/// (see [`vm.run_with()`](crate::vm::IntcodeVM::run_with))
+///
+/// Implemented for [`Vec`], [`VecDeque`] and closures taking the output value
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::io::OutputSink;
+/// let mut sink = Vec::new();
+/// sink.write_output(5);
+/// sink.write_output(4);
+///
+/// assert_eq!(sink, vec![5, 4]);
+/// ```
+pub trait OutputSink<T> {
+    /// Called with every value output by the VM, in order
+    fn write_output(&mut self, value: T);
+}
+
+/// An [InputSource] that never has any input available
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NoInput;
+
+impl<T> InputSource<T> for NoInput {
+    #[inline]
+    fn next_input(&mut self) -> Option<T> {
+        None
+    }
+}
+
+/// An [InputSource] yielding the values of an [Iterator]
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::{io::InputIter, IntcodeVM, StopReason};
+/// let mut vm = IntcodeVM::new([3, 7, 4, 7, 1105, 1, 0]);
+/// let mut outputs = Vec::new();
+///
+/// let stop = vm.run_with(&mut InputIter::new(1..=3), &mut outputs).unwrap();
+/// assert_eq!(stop, StopReason::WaitingForInput);
+/// assert_eq!(outputs, vec![1, 2, 3]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct InputIter<I> {
+    iter: I,
+}
+
+impl<I> InputIter<I>
+where
+    I: Iterator,
+{
+    /// Creates an [InputSource] yielding the values of `iter`
+    #[inline]
+    pub fn new<IT: IntoIterator<IntoIter = I>>(iter: IT) -> Self {
+        Self {
+            iter: iter.into_iter(),
+        }
+    }
+
+    /// Returns the wrapped iterator
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<T, I> InputSource<T> for InputIter<I>
+where
+    I: Iterator<Item = T>,
+{
+    #[inline]
+    fn next_input(&mut self) -> Option<T> {
+        self.iter.next()
+    }
+}
+
+impl<T> InputSource<T> for VecDeque<T> {
+    #[inline]
+    fn next_input(&mut self) -> Option<T> {
+        self.pop_front()
+    }
+}
+
+impl<T, F> InputSource<T> for F
+where
+    F: FnMut() -> Option<T>,
+{
+    #[inline]
+    fn next_input(&mut self) -> Option<T> {
+        self()
+    }
+}
+
+impl<T> OutputSink<T> for Vec<T> {
+    #[inline]
+    fn write_output(&mut self, value: T) {
+        self.push(value);
+    }
+}
+
+impl<T> OutputSink<T> for VecDeque<T> {
+    #[inline]
+    fn write_output(&mut self, value: T) {
+        self.push_back(value);
+    }
+}
+
+impl<T, F> OutputSink<T> for F
+where
+    F: FnMut(T),
+{
+    #[inline]
+    fn write_output(&mut self, value: T) {
+        self(value);
+    }
+}
+
+/// Bundles an [InputSource] and an [OutputSink] together so they can be used as a single device
+#[derive(Debug)]
+pub(crate) struct Wiring<'a, I: ?Sized, O: ?Sized> {
+    pub(crate) input: &'a mut I,
+    pub(crate) output: &'a mut O,
+}
+
+impl<T, I, O> InputSource<T> for Wiring<'_, I, O>
+where
+    I: InputSource<T> + ?Sized,
+    O: ?Sized,
+{
+    #[inline]
+    fn next_input(&mut self) -> Option<T> {
+        self.input.next_input()
+    }
+}
+
+impl<T, I, O> OutputSink<T> for Wiring<'_, I, O>
+where
+    I: ?Sized,
+    O: OutputSink<T> + ?Sized,
+{
+    #[inline]
+    fn write_output(&mut self, value: T) {
+        self.output.write_output(value);
+    }
+}
+
+/// Input queue of a VM, its first value kept apart so it can be borrowed as an [`Option`]
+/// (see [`vm.get_next_input()`](crate::vm::IntcodeVM::get_next_input))
+#[derive(Debug, Clone)]
+pub(crate) struct InputQueue<T> {
+    /// Value the next read instruction will consume, [`None`] only if `rest` is empty too
+    next: Option<T>,
+    rest: VecDeque<T>,
+}
+
+impl<T> Default for InputQueue<T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            next: None,
+            rest: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> InputQueue<T> {
+    #[inline]
+    pub(crate) const fn next(&self) -> &Option<T> {
+        &self.next
+    }
+
+    #[inline]
+    pub(crate) fn pop_front(&mut self) -> Option<T> {
+        let next = self.next.take();
+        self.next = self.rest.pop_front();
+        next
+    }
+
+    #[inline]
+    pub(crate) fn push_front(&mut self, value: T) {
+        if let Some(next) = self.next.replace(value) {
+            self.rest.push_front(next);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn push_back(&mut self, value: T) {
+        match self.next {
+            None => self.next = Some(value),
+            Some(_) => self.rest.push_back(value),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.next = None;
+        self.rest.clear();
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        usize::from(self.next.is_some()) + self.rest.len()
+    }
+
+    #[inline]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.next.iter().chain(&self.rest)
+    }
+}
+
+impl<T> Extend<T> for InputQueue<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            self.push_back(value);
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for InputQueue<T> {
+    #[inline]
+    fn from(values: Vec<T>) -> Self {
+        let mut queue = Self::default();
+        queue.extend(values);
+        queue
+    }
+}
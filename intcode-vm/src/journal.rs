@@ -0,0 +1,135 @@
+//! Undo journal of a [VM](crate::vm::IntcodeVM), used to execute a program backwards
+//!
+//! Once enabled with [`vm.enable_journal()`](crate::vm::IntcodeVM::enable_journal), the VM records
+//! what every instruction it executes overwrites, so [`vm.step_back()`](crate::vm::IntcodeVM::step_back)
+//! and [`vm.run_backwards_until()`](crate::vm::IntcodeVM::run_backwards_until) can undo them.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::IntcodeVM;
+//! // stores 3 at address 20, then multiplies it by 7
+//! let mut vm = IntcodeVM::new([1101, 1, 2, 20, 1002, 20, 7, 20, 99]);
+//! vm.enable_journal(100);
+//! vm.run_until_halt().unwrap();
+//! assert_eq!(vm.memory()[20], 21);
+//!
+//! // goes back to the instruction that wrote 21
+//! assert!(vm.run_backwards_until(|vm| vm.memory()[20] != 21));
+//! assert_eq!(vm.instruction_ptr(), 4);
+//! assert_eq!(vm.memory()[20], 3);
+//! ```
+
+use std::collections::VecDeque;
+
+/// What an instruction overwrote when it was executed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Entry<T> {
+    /// Instruction pointer before the instruction (i.e. its address)
+    pub(crate) instruction_ptr: usize,
+    /// Relative base before the instruction
+    pub(crate) relative_base: T,
+    /// Address written by the instruction and the value it replaced
+    pub(crate) write: Option<(usize, T)>,
+    /// Input consumed by the instruction
+    pub(crate) input: Option<T>,
+}
+
+/// The last instructions executed by a [VM](crate::vm::IntcodeVM), with what they overwrote
+///
+/// Only the last [`capacity()`](Journal::capacity) instructions are kept, older ones are forgotten.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Journal<T> {
+    capacity: usize,
+    entries: VecDeque<Entry<T>>,
+    /// Entry of the instruction being executed
+    current: Option<Entry<T>>,
+}
+
+impl<T> Journal<T> {
+    #[inline]
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    /// Returns the maximum number of instructions the journal remembers
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of instructions that can currently be undone
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no instruction can be undone
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Changes the capacity of the journal, forgetting the oldest instructions if needed
+    #[inline]
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Starts recording the instruction at `instruction_ptr`
+    #[inline]
+    pub(crate) fn begin(&mut self, instruction_ptr: usize, relative_base: T) {
+        self.current = Some(Entry {
+            instruction_ptr,
+            relative_base,
+            write: None,
+            input: None,
+        });
+    }
+
+    /// Records the instruction being executed replacing `old` at `address`
+    #[inline]
+    pub(crate) fn record_write(&mut self, address: usize, old: T) {
+        if let Some(current) = &mut self.current {
+            current.write = Some((address, old));
+        }
+    }
+
+    /// Records the instruction being executed consuming `input`
+    #[inline]
+    pub(crate) fn record_input(&mut self, input: T) {
+        if let Some(current) = &mut self.current {
+            current.input = Some(input);
+        }
+    }
+
+    /// Stops recording the instruction being executed,
+    /// `changed_state` is `false` if it did not change anything (nothing to undo)
+    #[inline]
+    pub(crate) fn end(&mut self, changed_state: bool) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+
+        if changed_state && self.capacity > 0 {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+
+            self.entries.push_back(current);
+        }
+    }
+
+    /// Removes the entry of the last executed instruction
+    #[inline]
+    pub(crate) fn pop(&mut self) -> Option<Entry<T>> {
+        self.entries.pop_back()
+    }
+}
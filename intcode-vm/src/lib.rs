@@ -28,16 +28,39 @@
 //! ]))
 //! ```
 
+pub mod arithmetic;
+pub mod asm;
+pub mod async_io;
+#[cfg(feature = "bigint")]
+pub mod bigint;
+pub mod breakpoint;
+pub mod disasm;
 pub mod error;
+pub mod fast;
+pub mod instruction;
+pub mod io;
+pub mod journal;
 pub mod memory;
+pub mod network;
+pub mod observer;
+pub mod pipeline;
+pub mod profile;
+pub mod snapshot;
 pub mod vm;
 
+#[cfg(feature = "bigint")]
+pub use bigint::PromotingIntcodeVM;
+pub use fast::FastIntcodeVM;
 pub use vm::IntcodeVM;
+pub use vm::Outputs;
+pub use vm::StopReason;
 pub use vm::VMResult;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use arithmetic::OverflowPolicy;
+    use instruction::{ArgMode, Destination, Opcode, Step};
 
     #[test]
     fn test_add() {
@@ -90,6 +113,28 @@ mod tests {
         assert!(vm.into_memory().memory_starts_with(&[3, 3, 104, 12345, 99]));
     }
 
+    #[test]
+    fn test_input_queue() {
+        let mut vm = IntcodeVM::from([3, 0, 4, 0, 3, 0, 4, 0, 99]);
+        vm.extend_inputs([1, 2]);
+        vm.push_input(3);
+        assert_eq!(vm.pending_inputs(), 3);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+        assert_eq!(vm.run().unwrap(), VMResult::Output(2));
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.peek_input(), Some(&3));
+    }
+
+    #[test]
+    fn test_input_queue_drained() {
+        let mut vm = IntcodeVM::from([3, 0, 3, 0, 4, 0, 99]);
+        vm.push_input(1);
+        assert_eq!(vm.run().unwrap(), VMResult::WaitingForInput);
+        assert_eq!(vm.pending_inputs(), 0);
+        vm.push_input(2);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(2));
+    }
+
     #[test]
     fn test_immediate_mul_into_halt() {
         let mut vm = IntcodeVM::from([1002, 4, 3, 4, 33]);
@@ -117,6 +162,1677 @@ mod tests {
         assert_eq!(vm.run().unwrap(), VMResult::Halted);
     }
 
+    #[test]
+    fn test_run_until_halt() {
+        let prog = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut vm = IntcodeVM::from(prog.iter().copied());
+        assert_eq!(vm.run_until_halt().unwrap(), prog);
+        assert_eq!(vm.run_until_halt().unwrap(), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_run_until_input() {
+        let mut vm = IntcodeVM::from([104, 1, 3, 0, 4, 0, 99]);
+        assert_eq!(
+            vm.run_until_input().unwrap(),
+            (vec![1], StopReason::WaitingForInput)
+        );
+        vm.push_input(2);
+        assert_eq!(vm.run_until_input().unwrap(), (vec![2], StopReason::Halted));
+    }
+
+    #[test]
+    fn test_run_with_queue_first() {
+        let mut vm = IntcodeVM::from([3, 9, 4, 9, 1105, 1, 0]);
+        vm.push_input(1);
+        let mut next = 1;
+        let mut input = || {
+            next += 1;
+            (next <= 3).then_some(next)
+        };
+        let mut outputs = std::collections::VecDeque::new();
+
+        let stop = vm.run_with(&mut input, &mut outputs).unwrap();
+        assert_eq!(stop, StopReason::WaitingForInput);
+        assert_eq!(outputs, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_outputs_stop_on_halt() {
+        let mut vm = IntcodeVM::from([104, 1, 104, 2, 99]);
+        let mut outputs = vm.outputs();
+        assert_eq!(outputs.next().unwrap().unwrap(), 1);
+        assert_eq!(outputs.next().unwrap().unwrap(), 2);
+        assert!(outputs.next().is_none());
+        assert!(outputs.next().is_none());
+        assert_eq!(outputs.stop_reason(), Some(StopReason::Halted));
+    }
+
+    #[test]
+    fn test_outputs_error() {
+        let mut vm = IntcodeVM::from([104, 1, 15]);
+        let mut outputs = vm.outputs();
+        assert_eq!(outputs.next().unwrap().unwrap(), 1);
+        assert!(outputs.next().unwrap().is_err());
+        assert!(outputs.next().is_none());
+        assert_eq!(outputs.stop_reason(), None);
+    }
+
+    #[test]
+    fn test_step_matches_run() {
+        let prog = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut stepped = IntcodeVM::from(prog.iter().copied());
+        let mut outputs = vec![];
+        loop {
+            let Step::Executed(executed) = stepped.step().unwrap() else {
+                panic!("Program does not read inputs");
+            };
+
+            match executed.opcode {
+                Opcode::WriteOutput => outputs.push(executed.operands[0].value.unwrap()),
+                Opcode::Halt => break,
+                _ => (),
+            }
+        }
+
+        assert_eq!(outputs, prog);
+        assert_eq!(stepped.relative_base(), &16);
+    }
+
+    #[test]
+    fn test_step_jump_not_taken() {
+        let mut vm = IntcodeVM::from([5, 5, -1, 99, 0, 0]);
+        let Step::Executed(executed) = vm.step().unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(executed.opcode, Opcode::JmpIfTrue);
+        assert_eq!(executed.operands[0].value, Some(0));
+        assert_eq!(executed.operands[1].value, None);
+        assert_eq!(executed.destination, None);
+        assert_eq!(executed.next_instruction_ptr, 3);
+    }
+
+    #[test]
+    fn test_step_read_input() {
+        let mut vm = IntcodeVM::from([203, 3, 99]);
+        vm.push_input(7);
+        let Step::Executed(executed) = vm.step().unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(executed.operands, vec![]);
+        assert_eq!(
+            executed.destination,
+            Some(Destination {
+                mode: ArgMode::Relative,
+                raw: 3,
+                address: 3,
+                value: 7
+            })
+        );
+        assert_eq!(vm.step().unwrap(), vm.step().unwrap());
+    }
+
+    #[test]
+    fn test_disassemble_data_fallback() {
+        let memory = memory::Memory::from([1199, 10104, 0, 4, 3, 1, 0]);
+        assert_eq!(
+            disasm::disassemble(&memory),
+            "0000: DATA 1199\n\
+             0001: DATA 10104\n\
+             0002: DATA 0\n\
+             0003: OUT [3]\n\
+             0005: DATA 1\n\
+             0006: DATA 0\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_relative() {
+        let memory = memory::Memory::from([209, -4, 22201, 1, -1, 0, 3, 7]);
+        assert_eq!(
+            disasm::disassemble(&memory),
+            "0000: ARB [rb-4]\n\
+             0002: ADD [rb+1], [rb-1] -> [rb+0]\n\
+             0006: IN -> [7]\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_paged() {
+        use memory::PAGE_SIZE;
+
+        let mut memory = memory::Memory::from([104, 7, 99]).into_paged();
+        memory.set(1_000_000_000, 99);
+        let listing = disasm::disassemble(&memory);
+        assert_eq!(
+            listing.lines().count(),
+            2 + (PAGE_SIZE - 3) + 1 + (1_000_000_000 % PAGE_SIZE) + 1
+        );
+        assert!(listing.starts_with("0000: OUT #7\n0002: HLT\n0003: DATA 0\n"));
+        assert!(listing.contains(&format!(
+            "{:04}: RESERVE {}\n",
+            PAGE_SIZE,
+            1_000_000_000 / PAGE_SIZE * PAGE_SIZE - PAGE_SIZE
+        )));
+        assert!(listing.ends_with("1000000000: HLT\n"));
+
+        // the listing still assembles back to the same values
+        let mut memory = memory::Memory::from([1101, 1, 2, 100_000, 99]).into_paged();
+        memory.set(100_000, 3);
+        let assembled = asm::assemble::<i64>(&disasm::disassemble(&memory)).unwrap();
+        assert!(assembled.iter().eq(memory.iter()));
+
+        // larger listings assemble into a paged memory
+        let mut vm = IntcodeVM::new(
+            memory::Memory::from([109, 1_000_000_000_000_000_i64, 203, 7, 204, 7, 99]).into_paged(),
+        );
+        vm.push_input(42);
+        vm.run_until_halt().unwrap();
+        let listing = disasm::disassemble(vm.memory());
+        let assembled = asm::assemble::<i64>(&listing).unwrap();
+        assert!(assembled.is_paged());
+        assert_eq!(assembled.len(), vm.memory().len());
+        assert_eq!(assembled[1_000_000_000_000_007], 42);
+        assert_eq!(disasm::disassemble(&assembled), listing);
+
+        let source = format!("HLT\nRESERVE {}", asm::MAX_CONTIGUOUS_SIZE);
+        let assembled = asm::assemble::<i64>(&source).unwrap();
+        assert!(assembled.is_paged());
+        assert_eq!(assembled.len(), asm::MAX_CONTIGUOUS_SIZE + 1);
+        assert!(!asm::assemble::<i64>("HLT\nRESERVE 1").unwrap().is_paged());
+    }
+
+    #[test]
+    fn test_assemble_disassemble_round_trip() {
+        let programs: [&[i64]; 4] = [
+            &[
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            &[1199, 10104, 0, 4, 3, 1, 0],
+            &[209, -4, 22201, 1, -1, 0, 3, 7, 11101, 1, 1, 5],
+            &[
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+            ],
+        ];
+
+        for program in programs {
+            let memory = memory::Memory::from(program.iter().copied());
+            let listing = disasm::disassemble(&memory);
+            let assembled = asm::assemble::<i64>(&listing).unwrap();
+            assert_eq!(assembled.iter().copied().collect::<Vec<_>>(), program);
+        }
+    }
+
+    #[test]
+    fn test_assemble_syntax() {
+        let memory = asm::assemble::<i64>(
+            "
+            start:  IN -> rb+1       ; unbracketed relative
+                    ARB #data
+                    ADD [rb], [rb + 1], [rb-1]
+            end:
+                    JZ #0, #start+2
+                    reserve 2
+            data:   DATA -1, end, rbx
+            rbx:    hlt
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            memory.iter().copied().collect::<Vec<_>>(),
+            [203, 1, 109, 13, 22201, 0, 1, -1, 1106, 0, 2, 0, 0, -1, 8, 16, 99]
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        use error::AsmErrorKind;
+
+        let err = asm::assemble::<i64>("ADD #1, #2").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        assert!(matches!(
+            err.kind,
+            AsmErrorKind::WrongArgCount {
+                expected: 3,
+                found: 2,
+                ..
+            }
+        ));
+
+        let err = asm::assemble::<i64>("a: HLT\n  a: HLT").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+        assert_eq!(err.kind, AsmErrorKind::DuplicateLabel("a".to_string()));
+
+        let err = asm::assemble::<i64>("OUT #1 -> [2]").unwrap_err();
+        assert_eq!(
+            err.kind,
+            AsmErrorKind::UnexpectedDestination("OUT".to_string())
+        );
+
+        let err = asm::assemble::<i64>("OUT {1}").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+        assert_eq!(err.kind, AsmErrorKind::InvalidValue("{1}".to_string()));
+
+        let err = asm::assemble::<i64>("HLT\n0002: HLT").unwrap_err();
+        assert_eq!(
+            err.kind,
+            AsmErrorKind::AddressMismatch {
+                annotated: 2,
+                actual: 1
+            }
+        );
+
+        let err = asm::assemble::<i64>("NOP").unwrap_err();
+        assert_eq!(err.kind, AsmErrorKind::UnknownMnemonic("NOP".to_string()));
+        let err = asm::assemble::<i64>("HLT\nRESERVE 18446744073709551615").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+        assert_eq!(err.kind, AsmErrorKind::ProgramTooLarge(usize::MAX));
+
+        let err = asm::assemble::<i8>("RESERVE 127\nend: DATA end+1").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 11));
+        assert_eq!(err.kind, AsmErrorKind::ValueOutOfRange("end".to_string()));
+
+        let err = asm::assemble::<i64>("OUT [rb--1]").unwrap_err();
+        assert_eq!(err.kind, AsmErrorKind::InvalidValue("-1".to_string()));
+    }
+
+    #[test]
+    fn test_assemble_extreme_values() {
+        use error::AsmErrorKind;
+
+        let memory = asm::assemble::<i64>(
+            "
+            start:  OUT [rb-9223372036854775808]
+                    DATA -9223372036854775808, start-9223372036854775808, start+9223372036854775807
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            memory.iter().copied().collect::<Vec<_>>(),
+            [204, i64::MIN, i64::MIN, i64::MIN, i64::MAX]
+        );
+
+        let err = asm::assemble::<i64>("DATA 0\nend: DATA end+9223372036854775807").unwrap_err();
+        assert_eq!(err.kind, AsmErrorKind::ValueOutOfRange("end".to_string()));
+        let err = asm::assemble::<i64>("start: OUT [rb-start-9223372036854775808]").unwrap_err();
+        assert_eq!(err.kind, AsmErrorKind::ValueOutOfRange("start".to_string()));
+    }
+
+    #[test]
+    fn test_observer_callbacks() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+
+        impl observer::Observer<i64> for Recorder {
+            fn before_instruction(&mut self, address: usize, opcode: Opcode) {
+                self.0.push(format!("{address}: {}", opcode.mnemonic()));
+            }
+
+            fn on_memory_write(&mut self, address: usize, old: &i64, new: &i64) {
+                self.0.push(format!("[{address}] {old} -> {new}"));
+            }
+
+            fn on_relative_base_change(&mut self, old: &i64, new: &i64) {
+                self.0.push(format!("rb {old} -> {new}"));
+            }
+
+            fn on_input(&mut self, value: &i64) {
+                self.0.push(format!("in {value}"));
+            }
+
+            fn on_output(&mut self, value: &i64) {
+                self.0.push(format!("out {value}"));
+            }
+        }
+
+        let mut vm =
+            IntcodeVM::from([109, 3, 203, 7, 204, 7, 99]).with_observer(Recorder::default());
+        assert_eq!(vm.run().unwrap(), VMResult::WaitingForInput);
+        assert_eq!(vm.step().unwrap(), Step::WaitingForInput);
+        vm.push_input(5);
+        assert_eq!(vm.run_until_halt().unwrap(), vec![5]);
+        // every instruction is reported once, even if the VM stops on it
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert!(vm.step().is_ok());
+        assert_eq!(
+            vm.observer().0,
+            [
+                "0: ARB",
+                "rb 0 -> 3",
+                "2: IN",
+                "in 5",
+                "[10] 0 -> 5",
+                "4: OUT",
+                "out 5",
+                "6: HLT"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut vm = IntcodeVM::from([109, 7, 203, 0, 204, 0, 99]);
+        vm.extend_inputs([5, 6]);
+        assert!(matches!(vm.step().unwrap(), Step::Executed(_)));
+
+        let snapshot = vm.snapshot();
+        assert_eq!(snapshot.version(), snapshot::SNAPSHOT_VERSION);
+        assert_eq!(snapshot.instruction_ptr(), 2);
+        assert_eq!(snapshot.relative_base(), &7);
+        assert_eq!(snapshot.pending_inputs(), [5, 6]);
+
+        assert_eq!(vm.run_until_halt().unwrap(), vec![5]);
+
+        let mut restored = IntcodeVM::restore(snapshot).unwrap();
+        assert_eq!(restored.pending_inputs(), 2);
+        assert_eq!(restored.overflow_policy(), OverflowPolicy::Checked);
+        assert_eq!(restored.run_until_halt().unwrap(), vec![5]);
+        assert!(restored
+            .into_memory()
+            .memory_starts_with(vm.memory().iter()));
+
+        // a paged memory only stores whole pages, but keeps its length
+        let mut program = vec![0; 150];
+        program[149] = 99;
+        let vm = IntcodeVM::new(memory::Memory::from(program).into_paged());
+        let restored = IntcodeVM::restore(vm.snapshot()).unwrap();
+        assert_eq!(restored.memory().len(), 150);
+        assert_eq!(
+            disasm::disassemble(restored.memory()),
+            disasm::disassemble(vm.memory())
+        );
+
+        // outputs i64::MAX + 1
+        let mut vm = IntcodeVM::new([1101, i64::MAX, 1, 7, 4, 7, 99, 0]);
+        vm.set_overflow_policy(OverflowPolicy::Wrapping);
+        let snapshot = vm.snapshot();
+        assert_eq!(snapshot.overflow_policy(), OverflowPolicy::Wrapping);
+        let mut restored = IntcodeVM::restore(snapshot).unwrap();
+        assert_eq!(restored.run().unwrap(), VMResult::Output(i64::MIN));
+    }
+
+    #[test]
+    fn test_restore_incompatible_snapshot() {
+        let mut snapshot = IntcodeVM::from([99]).snapshot();
+        snapshot.version = snapshot::SNAPSHOT_VERSION + 1;
+
+        match IntcodeVM::restore(snapshot).map_err(|error| error.kind) {
+            Err(error::VMErrorKind::IncompatibleSnapshot { version, supported }) => {
+                assert_eq!(version, snapshot::SNAPSHOT_VERSION + 1);
+                assert_eq!(supported, snapshot::SNAPSHOT_VERSION);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_serde() {
+        let mut vm = IntcodeVM::from([3, 9, 4, 9, 1105, 1, 0, 99, 0, 0]);
+        vm.extend_inputs([1, 2, 3]);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+
+        let json = serde_json::to_string(&vm.snapshot()).unwrap();
+        let snapshot: snapshot::Snapshot<i64> = serde_json::from_str(&json).unwrap();
+        let mut restored = IntcodeVM::restore(snapshot).unwrap();
+        assert_eq!(restored.run().unwrap(), VMResult::Output(2));
+        assert_eq!(restored.run().unwrap(), VMResult::Output(3));
+
+        let json = json.replacen(
+            &format!("\"version\":{}", snapshot::SNAPSHOT_VERSION),
+            "\"version\":0",
+            1,
+        );
+        let snapshot: snapshot::Snapshot<i64> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(
+            IntcodeVM::restore(snapshot).map_err(|error| error.kind),
+            Err(error::VMErrorKind::IncompatibleSnapshot { version: 0, .. })
+        ));
+    }
+
+    #[cfg(all(feature = "serde", feature = "bigint"))]
+    #[test]
+    fn test_bigint_snapshot_serde() {
+        use bigint::BigInt;
+
+        let mut vm: IntcodeVM<BigInt> = "3,9,4,9,1105,1,0,99,0,0".parse().unwrap();
+        let big: BigInt = "100000000000000000000".parse().unwrap();
+        vm.extend_inputs([big.clone(), big.clone() + 1]);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(big.clone()));
+
+        let json = serde_json::to_string(&vm.snapshot()).unwrap();
+        let snapshot: snapshot::Snapshot<BigInt> = serde_json::from_str(&json).unwrap();
+        let mut restored = IntcodeVM::restore(snapshot).unwrap();
+        assert_eq!(restored.run().unwrap(), VMResult::Output(big + 1));
+    }
+
+    #[test]
+    fn test_journal_step_back() {
+        let prog = [109, 10, 22201, 0, 1, 2, 203, 3, 99, 0, 4, 5, 0, 0];
+        let mut vm = IntcodeVM::from(prog);
+        vm.enable_journal(10);
+        assert_eq!(
+            vm.run_with(&mut std::collections::VecDeque::from([7]), &mut Vec::new())
+                .unwrap(),
+            StopReason::Halted
+        );
+        assert_eq!(vm.journal().unwrap().len(), 3);
+        assert_eq!(vm.relative_base(), &10);
+        assert!(vm
+            .memory()
+            .memory_starts_with(&[109, 10, 22201, 0, 1, 2, 203, 3, 99, 0, 4, 5, 9, 7]));
+
+        // the input taken from the source is put back in the queue
+        assert!(vm.step_back());
+        assert_eq!(vm.instruction_ptr(), 6);
+        assert_eq!(vm.peek_input(), Some(&7));
+        assert_eq!(vm.memory()[13], 0);
+
+        assert!(vm.run_backwards_until(|vm| vm.relative_base() == &0));
+        assert_eq!(vm.instruction_ptr(), 0);
+        assert!(vm.memory().memory_starts_with(&prog));
+        assert!(!vm.step_back());
+
+        // replays the same execution
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.memory()[13], 7);
+    }
+
+    #[test]
+    fn test_journal_capacity() {
+        // counts down from 5 at address 9
+        let mut vm = IntcodeVM::from([1001, 9, -1, 9, 1005, 9, 0, 99, 0, 5]);
+        assert!(!vm.step_back());
+
+        vm.enable_journal(3);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.journal().unwrap().len(), 3);
+
+        assert!(!vm.run_backwards_until(|vm| vm.memory()[9] == 3));
+        assert_eq!(vm.memory()[9], 1);
+        assert_eq!(vm.instruction_ptr(), 4);
+
+        vm.enable_journal(1);
+        assert_eq!(vm.journal().unwrap().capacity(), 1);
+        vm.disable_journal();
+        assert!(vm.journal().is_none());
+    }
+
+    #[test]
+    fn test_budget_exhausted() {
+        // outputs 1, 2, 3, ... forever
+        let prog = [1001, 9, 1, 9, 4, 9, 1105, 1, 0, 0];
+        let mut vm = IntcodeVM::from(prog);
+        vm.set_run_budget(Some(100));
+        match vm.run_until_halt().map_err(|error| error.kind) {
+            Err(error::VMErrorKind::Stopped { reason, outputs }) => {
+                assert_eq!(reason, StopReason::BudgetExhausted);
+                assert_eq!(outputs.len(), 33);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let mut vm = IntcodeVM::from(prog);
+        vm.set_total_budget(Some(9));
+        let mut outputs = vm.outputs();
+        assert_eq!(
+            outputs.by_ref().collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(outputs.stop_reason(), Some(StopReason::BudgetExhausted));
+        assert_eq!(vm.executed_instructions(), 9);
+
+        // halting does not use the budget
+        let mut vm = IntcodeVM::from([104, 1, 99]);
+        vm.set_total_budget(Some(1));
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.executed_instructions(), 1);
+    }
+
+    #[test]
+    fn test_paged_memory() {
+        let mut dense = memory::Memory::from([1, 0, 0, 3, 99]);
+        let mut paged = dense.clone().into_paged();
+        assert!(paged.is_paged());
+        assert_eq!(paged.len(), 5);
+
+        for (address, value) in [(3, 7), (2000, 5), (1023, 1), (1024, 2), (10, 0)] {
+            dense.set(address, value);
+            paged.set(address, value);
+        }
+
+        assert_eq!(paged.len(), dense.len());
+        assert!(paged.iter().eq(dense.iter()));
+        assert!(paged.memory_starts_with(dense.iter()));
+        assert_eq!(paged[2000], 5);
+        assert_eq!(paged[2001], 0);
+        assert_eq!(paged.get(usize::MAX), &0);
+
+        // the length of the memory would not fit in a usize
+        for mut memory in [dense, paged] {
+            assert!(matches!(
+                memory.try_set(usize::MAX, 1).map_err(|error| error.kind),
+                Err(error::VMErrorKind::MemoryLimitExceeded {
+                    address: usize::MAX,
+                    limit: usize::MAX
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_far_relative_write() {
+        // sets the relative base to 10^15, reads an input there and outputs it
+        let prog = [109, 1_000_000_000_000_000_i64, 203, 7, 204, 7, 99];
+        let mut vm = IntcodeVM::new(memory::Memory::from(prog).into_paged());
+        vm.push_input(42);
+        assert_eq!(vm.run_until_halt().unwrap(), vec![42]);
+        assert_eq!(vm.memory().len(), 1_000_000_000_000_008);
+
+        let snapshot = vm.snapshot();
+        let restored = IntcodeVM::restore(snapshot).unwrap();
+        assert!(restored.memory().is_paged());
+        assert_eq!(restored.memory()[1_000_000_000_000_007], 42);
+        assert_eq!(restored.memory().len(), vm.memory().len());
+
+        let mut snapshot = vm.snapshot();
+        snapshot.memory.push((usize::MAX - 1, vec![1, 2]));
+        assert!(matches!(
+            IntcodeVM::restore(snapshot).map_err(|error| error.kind),
+            Err(error::VMErrorKind::MemoryLimitExceeded { .. })
+        ));
+
+        let mut memory = memory::Memory::from(prog);
+        memory.set_limit(Some(1 << 30));
+        let mut vm = IntcodeVM::new(memory);
+        vm.push_input(42);
+        match vm.run().map_err(|error| error.kind) {
+            Err(error::VMErrorKind::MemoryLimitExceeded { address, limit }) => {
+                assert_eq!(address, 1_000_000_000_000_007);
+                assert_eq!(limit, 1 << 30);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // nothing was consumed nor written
+        assert_eq!(vm.instruction_ptr(), 2);
+        assert_eq!(vm.pending_inputs(), 1);
+    }
+
+    /// Fixed size memory counting how many values are written
+    struct FixedMemory {
+        values: [i64; 16],
+        writes: usize,
+    }
+
+    impl memory::MemoryBackend<i64> for FixedMemory {
+        fn read(&self, address: usize) -> i64 {
+            self.values.get(address).copied().unwrap_or(0)
+        }
+
+        fn try_set(&mut self, address: usize, value: i64) -> error::Result<(), i64> {
+            self.check_write(address)?;
+            self.values[address] = value;
+            self.writes += 1;
+            Ok(())
+        }
+
+        fn check_write(&self, address: usize) -> error::Result<(), i64> {
+            match address < self.values.len() {
+                true => Ok(()),
+                false => Err(error::VMErrorKind::MemoryLimitExceeded {
+                    address,
+                    limit: self.values.len(),
+                }
+                .into()),
+            }
+        }
+
+        fn len(&self) -> usize {
+            self.values.len()
+        }
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        let mut values = [0; 16];
+        // reads 2 values, outputs their product and writes it past the end of the memory
+        values[..14].copy_from_slice(&[3, 14, 3, 15, 2, 14, 15, 15, 4, 15, 1101, 0, 0, 99]);
+        let mut vm = IntcodeVM::from_backend(FixedMemory { values, writes: 0 });
+        vm.extend_inputs([6, 7]);
+
+        assert!(disasm::disassemble(vm.memory()).starts_with("0000: IN -> [14]\n"));
+        assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+        assert_eq!(vm.memory().writes, 3);
+        assert_eq!(vm.memory().values[15], 42);
+
+        assert!(matches!(
+            vm.step().map_err(|error| error.kind),
+            Err(error::VMErrorKind::MemoryLimitExceeded {
+                address: 99,
+                limit: 16
+            })
+        ));
+        assert_eq!(vm.into_memory().writes, 3);
+    }
+
+    /// Memory where writing `value` to the last cell also writes `value + 1` to address 2
+    struct MappedMemory([i64; 16]);
+
+    impl memory::MemoryBackend<i64> for MappedMemory {
+        fn read(&self, address: usize) -> i64 {
+            self.0.get(address).copied().unwrap_or(0)
+        }
+
+        fn try_set(&mut self, address: usize, value: i64) -> error::Result<(), i64> {
+            self.0[address] = value;
+            if address == 15 {
+                self.0[2] = value + 1;
+            }
+            Ok(())
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[test]
+    fn test_memory_mapped_backend() {
+        let mut values = [0; 16];
+        // loop: mem[15] = 0 + mem[2], output mem[15]
+        values[..9].copy_from_slice(&[1101, 0, 1, 15, 4, 15, 1105, 1, 0]);
+        let mut vm = IntcodeVM::from_backend(MappedMemory(values));
+
+        // the instruction sees its argument change
+        for expected in 1..=3 {
+            assert_eq!(vm.run().unwrap(), VMResult::Output(expected));
+        }
+    }
+
+    /// Memory whose address 100 is not stored: reading it gives the sum of every other value
+    struct SumRegisterMemory(memory::Memory<i64>);
+
+    impl SumRegisterMemory {
+        const REGISTER: usize = 100;
+    }
+
+    impl memory::MemoryBackend<i64> for SumRegisterMemory {
+        fn read(&self, address: usize) -> i64 {
+            match address {
+                Self::REGISTER => self.0.iter().sum(),
+                _ => *self.0.get(address),
+            }
+        }
+
+        fn try_set(&mut self, address: usize, value: i64) -> error::Result<(), i64> {
+            self.check_write(address)?;
+            self.0.try_set(address, value)
+        }
+
+        fn check_write(&self, address: usize) -> error::Result<(), i64> {
+            match address {
+                Self::REGISTER => Err(error::VMErrorKind::ReadOnlyAddress(address).into()),
+                _ => self.0.check_write(address),
+            }
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[test]
+    fn test_computed_memory_backend() {
+        // outputs the register, writes 1 to address 10, outputs the register again
+        let memory = memory::Memory::from([4, 100, 1101, 1, 0, 10, 4, 100, 99, 0, 0]);
+        let mut vm = IntcodeVM::from_backend(SumRegisterMemory(memory));
+        assert_eq!(vm.run_until_halt().unwrap(), vec![1419, 1420]);
+
+        let mut vm = IntcodeVM::from_backend(SumRegisterMemory([1101, 0, 0, 100].into()));
+        assert!(matches!(
+            vm.run().map_err(|error| error.kind),
+            Err(error::VMErrorKind::ReadOnlyAddress(100))
+        ));
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // replaces its first instruction (add) with a mul, then runs it again
+        let mut vm = IntcodeVM::new([
+            1101, 2, 3, 30, // mem[30] = 2 + 3
+            4, 30, // output mem[30]
+            1008, 0, 1102, 31, // mem[31] = mem[0] == 1102
+            1005, 31, 20, // if mem[31] jump to 20
+            1101, 0, 1102, 0, // mem[0] = 1102
+            1105, 1, 0,  // jump to 0
+            99, // halt
+        ]);
+        assert_eq!(vm.run_until_halt().unwrap(), vec![5, 6]);
+
+        // outputs 2 + 3 forever
+        let mut vm = IntcodeVM::new([1101, 2, 3, 10, 4, 10, 1105, 1, 0]);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(5));
+        assert_eq!(vm.run().unwrap(), VMResult::Output(5));
+        vm.memory_mut().set(0, 1102);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(6));
+
+        // outputs 2 + 3, replaces the add with a mul and outputs 2 * 3 forever
+        let mut vm = IntcodeVM::new([1101, 2, 3, 20, 4, 20, 1101, 0, 1102, 0, 1105, 1, 0]);
+        vm.enable_journal(10);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(5));
+        assert_eq!(vm.run().unwrap(), VMResult::Output(6));
+        assert!(vm.run_backwards_until(|vm| vm.instruction_ptr() == 0 && vm.memory()[0] == 1101));
+        assert_eq!(vm.run().unwrap(), VMResult::Output(5));
+    }
+
+    #[test]
+    fn test_error_context() {
+        // reads an input, then jumps to address -1
+        let mut vm = IntcodeVM::new([109, 7, 3, 12, 1105, 9, 9, 2, 0, 1106, 0, -1]);
+        let error = vm.run_until_halt().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            error::VMErrorKind::MissingInput { .. }
+        ));
+        let context = error.context.unwrap();
+        assert_eq!(context.instruction_ptr, 2);
+        assert_eq!(context.opcode, 3);
+        assert_eq!(context.relative_base, 7);
+        assert_eq!(context.window_start, 0);
+        assert_eq!(context.window, vec![109, 7, 3, 12, 1105, 9, 9, 2, 0, 1106]);
+
+        vm.push_input(42);
+        let error = vm.run().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            error::VMErrorKind::CannotCastToUsize(-1)
+        ));
+        assert_eq!(error.arg_num, Some(2));
+        assert_eq!(
+            error.to_string(),
+            "Could not cast -1 to usize (address is cast to usize before being used) \
+             (instruction at address 9, argument n°2)"
+        );
+        // the MUL at address 7 would overlap the failing instruction, it is shown as data
+        assert_eq!(
+            format!("{error:#}")
+                .lines()
+                .skip(1)
+                .take(6)
+                .collect::<Vec<_>>(),
+            [
+                "relative base: 7",
+                "  0005: ARB [9]",
+                "  0007: DATA 2",
+                "  0008: DATA 0",
+                "> 0009: JZ #0, #-1",
+                "  0012: DATA 42",
+            ]
+        );
+
+        // the write of the ADD is not allowed
+        let mut memory = memory::Memory::from([15, 0, 0, 99, 1101, 1, 2, 100, 99]);
+        memory.set_limit(Some(100));
+        let mut vm = IntcodeVM::new(memory);
+        let error = vm.run().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            error::VMErrorKind::UnknownInstruction(15)
+        ));
+        assert_eq!(error.arg_num, None);
+        assert!(format!("{error:#}").contains("\n> 0000: DATA 15\n  0001: DATA 0\n"));
+
+        vm.memory_mut().set(0, 1105);
+        vm.memory_mut().set(1, 1);
+        vm.memory_mut().set(2, 4);
+        let error = vm.step().and_then(|_| vm.step()).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            error::VMErrorKind::MemoryLimitExceeded {
+                address: 100,
+                limit: 100
+            }
+        ));
+        assert_eq!(error.arg_num, Some(3));
+        assert_eq!(error.context.unwrap().instruction_ptr, 4);
+
+        let error = vm.run_until_halt().unwrap_err();
+        assert_eq!(error.to_string().lines().count(), 1);
+
+        let mut snapshot = vm.snapshot();
+        snapshot.version += 1;
+        let error = IntcodeVM::restore(snapshot).unwrap_err();
+        assert!(error.context.is_none());
+        assert_eq!(format!("{error:#}"), error.to_string());
+    }
+
+    #[test]
+    fn test_error_context_far_away() {
+        // jumps far past the end of the memory, where every value is 0
+        let mut vm: IntcodeVM<i64> = [1105, 1, 1_000_000_000_000].into();
+        let error = vm.run().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            error::VMErrorKind::UnknownInstruction(0)
+        ));
+        assert_eq!(
+            error.context.as_ref().unwrap().instruction_ptr,
+            1_000_000_000_000
+        );
+        assert!(format!("{error:#}").contains("\n> 1000000000000: DATA 0\n"));
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        // outputs (i64::MAX + 1) * 2
+        let mut vm = IntcodeVM::new([1001, 11, 1, 12, 1002, 12, 2, 12, 4, 12, 99, i64::MAX, 0]);
+        assert_eq!(vm.overflow_policy(), OverflowPolicy::Checked);
+        assert!(matches!(
+            vm.run().map_err(|error| error.kind),
+            Err(error::VMErrorKind::ArithmeticOverflow {
+                ip: 0,
+                opcode: Opcode::Add
+            })
+        ));
+        assert_eq!(vm.instruction_ptr(), 0);
+
+        vm.memory_mut().set(2, 0);
+        assert!(matches!(
+            vm.run().map_err(|error| error.kind),
+            Err(error::VMErrorKind::ArithmeticOverflow {
+                ip: 4,
+                opcode: Opcode::Mul
+            })
+        ));
+
+        vm.set_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(-2));
+
+        let mut vm = IntcodeVM::new([109, -1, 109, i64::MIN, 99]);
+        assert!(matches!(
+            vm.run().map_err(|error| error.kind),
+            Err(error::VMErrorKind::ArithmeticOverflow {
+                ip: 2,
+                opcode: Opcode::AddRelativeBase
+            })
+        ));
+        assert_eq!(vm.relative_base(), &-1);
+
+        let mut saturating = IntcodeVM::new([109, -1, 109, i64::MIN, 99]);
+        saturating.set_overflow_policy(OverflowPolicy::Saturating);
+        let mut wrapping = saturating.fork();
+        wrapping.set_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(saturating.run().unwrap(), VMResult::Halted);
+        assert_eq!(saturating.relative_base(), &i64::MIN);
+        assert_eq!(wrapping.run().unwrap(), VMResult::Halted);
+        assert_eq!(wrapping.relative_base(), &i64::MAX);
+
+        // a relative address that overflows is an error, whatever the policy
+        let program = [109, i64::MAX, 204, 1, 99];
+        for policy in [
+            OverflowPolicy::Checked,
+            OverflowPolicy::Wrapping,
+            OverflowPolicy::Saturating,
+        ] {
+            let mut vm = IntcodeVM::new(program);
+            vm.set_overflow_policy(policy);
+            let error = vm.run().unwrap_err();
+            assert!(matches!(
+                error.kind,
+                error::VMErrorKind::ArithmeticOverflow {
+                    ip: 2,
+                    opcode: Opcode::WriteOutput
+                }
+            ));
+            assert_eq!(error.arg_num, Some(1));
+
+            let mut vm = FastIntcodeVM::new(program);
+            vm.set_overflow_policy(policy);
+            let error = vm.run().unwrap_err();
+            assert!(matches!(
+                error.kind,
+                error::VMErrorKind::ArithmeticOverflow {
+                    ip: 2,
+                    opcode: Opcode::WriteOutput
+                }
+            ));
+            assert_eq!(error.arg_num, Some(1));
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint() {
+        use bigint::BigInt;
+
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut vm: IntcodeVM<BigInt> = quine.parse().unwrap();
+        let expected = quine.parse::<memory::Memory<BigInt>>().unwrap();
+        assert!(vm.run_until_halt().unwrap().iter().eq(expected.iter()));
+
+        let mut vm: IntcodeVM<BigInt> = "1102,34915192,34915192,7,4,7,99,0".parse().unwrap();
+        let output = vm.run_until_halt().unwrap();
+        assert_eq!(output, vec![BigInt::from(1_219_070_632_396_864_i64)]);
+        assert_eq!(output[0].to_string().len(), 16);
+
+        let mut vm: IntcodeVM<BigInt> = "104,1125899906842624,99".parse().unwrap();
+        assert_eq!(
+            vm.run_until_halt().unwrap(),
+            vec![BigInt::from(1_125_899_906_842_624_i64)]
+        );
+
+        // addresses go through to_usize
+        let mut vm: IntcodeVM<BigInt> = "109,100000000000000000000,99".parse().unwrap();
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        let mut vm: IntcodeVM<BigInt> = "4,100000000000000000000,99".parse().unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(error::VMError {
+                kind: error::VMErrorKind::CannotCastToUsize(address),
+                ..
+            })
+                if address.to_string() == "100000000000000000000"
+        ));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_promoting_vm() {
+        use bigint::BigInt;
+
+        // outputs 2^n for n from 1 to 100, then adds 2^100 to its relative base twice
+        let program = asm::assemble::<i64>(
+            "
+                loop:   MUL [value], #2 -> [value]
+                        OUT [value]
+                        ADD [count], #-1 -> [count]
+                        JNZ [count], #loop
+                        ARB [value]
+                        ARB [value]
+                        HLT
+                value:  DATA 1
+                count:  DATA 100
+            ",
+        )
+        .unwrap();
+
+        let mut vm = PromotingIntcodeVM::new(program.clone());
+        let outputs = vm.run_until_halt().unwrap();
+        assert!(vm.is_promoted());
+        let expected = (1..=100)
+            .map(|n| BigInt::from(2).pow(n))
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, expected);
+        assert_eq!(vm.relative_base(), BigInt::from(2).pow(101));
+
+        let mut generic = IntcodeVM::new(program.iter().map(|&value| BigInt::from(value)));
+        assert_eq!(generic.run_until_halt().unwrap(), expected);
+        assert!(vm.into_memory().iter().eq(generic.memory().iter()));
+
+        // stays with i64 values as long as they fit
+        let mut vm = PromotingIntcodeVM::from([3, 0, 4, 0, 99]);
+        vm.push_input(i64::MAX);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(BigInt::from(i64::MAX)));
+        assert!(!vm.is_promoted());
+
+        let mut vm = PromotingIntcodeVM::from([3, 0, 4, 0, 99]);
+        vm.push_input(BigInt::from(i64::MAX) + 1);
+        assert!(vm.is_promoted());
+        assert_eq!(vm.pending_inputs(), 1);
+        assert_eq!(
+            vm.run().unwrap(),
+            VMResult::Output(BigInt::from(i64::MAX) + 1)
+        );
+
+        let vm: PromotingIntcodeVM = "104,1125899906842624,99".parse().unwrap();
+        assert!(!vm.is_promoted());
+        let mut vm: PromotingIntcodeVM = "104,100000000000000000000,99".parse().unwrap();
+        assert!(vm.is_promoted());
+        assert_eq!(vm.get(1).to_string(), "100000000000000000000");
+        assert_eq!(vm.run().unwrap(), VMResult::Output(vm.get(1)));
+
+        // stops for the same reasons as the generic VM, the budget covers the promotion
+        let mut vm = PromotingIntcodeVM::new(program.clone());
+        vm.set_run_budget(Some(150));
+        let (outputs, stop) = vm.run_until_input().unwrap();
+        assert_eq!(stop, StopReason::BudgetExhausted);
+        assert_eq!(outputs, expected[..38]);
+        assert!(!vm.is_promoted());
+        match vm.run_until_halt().map_err(|error| error.kind) {
+            Err(error::VMErrorKind::Stopped {
+                reason: StopReason::BudgetExhausted,
+                outputs,
+            }) => assert_eq!(outputs, expected[38..75]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(vm.is_promoted());
+
+        // other errors are kept
+        let mut vm = PromotingIntcodeVM::from([4, -1, 99]);
+        assert!(matches!(
+            vm.run(),
+            Err(error::VMError {
+                kind: error::VMErrorKind::CannotCastToUsize(address),
+                ..
+            }) if address == BigInt::from(-1)
+        ));
+    }
+
+    /// Programs (with their inputs) run by both [IntcodeVM] and [FastIntcodeVM]
+    /// with every [OverflowPolicy] and several run budgets in [test_fast_vm_matches_generic_vm]
+    const COMPATIBILITY_PROGRAMS: &[(&[i64], &[i64])] = &[
+        (&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], &[]),
+        (&[1002, 4, 3, 4, 33], &[]),
+        (&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[8]),
+        (&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[3]),
+        (
+            &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            &[0],
+        ),
+        (&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[5]),
+        (
+            &[
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ],
+            &[7],
+        ),
+        (
+            &[
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            &[],
+        ),
+        (&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]),
+        (&[104, 1125899906842624, 99], &[]),
+        (&[109, 19, 203, 2000, 204, 2000, 99], &[42]),
+        (&[3, 9, 4, 9, 4, 9, 1105, 1, 0], &[1, 2, 3]),
+        (
+            &[
+                1101, 2, 3, 30, 4, 30, 1008, 0, 1102, 31, 1005, 31, 20, 1101, 0, 1102, 0, 1105, 1,
+                0, 99,
+            ],
+            &[],
+        ),
+        (&[1101, 1, 1, 100_000, 4, 100_000, 99], &[]),
+        (&[20099], &[]),
+        (&[15], &[]),
+        (&[-1], &[]),
+        (&[100_000], &[]),
+        (&[301, 0, 0, 0, 99], &[]),
+        (&[30001, 0, 0, 0, 99], &[]),
+        (&[11101, 1, 1, 0, 99], &[]),
+        (&[103, 0, 99], &[1]),
+        (&[1, -1, 0, 0, 99], &[]),
+        (&[109, -10, 204, 0, 99], &[]),
+        (&[1105, 1, -5], &[]),
+        (&[1101, i64::MAX, 1, 7, 4, 7, 99, 0], &[]),
+        (&[1102, i64::MIN, -1, 7, 4, 7, 99, 0], &[]),
+        (&[109, i64::MIN, 109, -1, 99], &[]),
+        (&[11101, i64::MAX, 1, 0, 99], &[]),
+        (&[11102, i64::MAX, 2, 0, 99], &[]),
+        // several errors are possible, the first one found must be the same
+        (&[11107, 1, 2, 0, 99], &[]),
+        (&[103, 0, 99], &[]),
+        (&[109, i64::MAX, 204, 1, 99], &[]),
+        (&[109, i64::MAX, 209, 1, 99], &[]),
+        (&[109, i64::MAX, 21101, 1, 1, 1, 99], &[]),
+        (&[109, i64::MAX, 22201, 1, 1, 1, 99], &[]),
+        (&[109, i64::MAX, 1201, 1, i64::MAX, 1, 99], &[]),
+        (&[109, i64::MAX, 21201, 1, i64::MAX, 1, 99], &[]),
+        (&[109, i64::MAX, 2105, 1, 1, 99], &[]),
+        (&[109, i64::MAX, 203, 1, 99], &[]),
+        (&[109, -1, 21101, 1, 1, 0, 99], &[]),
+    ];
+
+    #[test]
+    fn test_fast_vm_matches_generic_vm() {
+        let policies = [
+            OverflowPolicy::Checked,
+            OverflowPolicy::Wrapping,
+            OverflowPolicy::Saturating,
+        ];
+        let budgets = [None, Some(0), Some(3)];
+        let cases = COMPATIBILITY_PROGRAMS.iter().flat_map(|case| {
+            policies
+                .into_iter()
+                .flat_map(move |policy| budgets.map(|budget| (case, policy, budget)))
+        });
+        for ((program, inputs), policy, budget) in cases {
+            let new_vms = || {
+                let mut generic = IntcodeVM::new(program.iter().copied());
+                let mut fast = FastIntcodeVM::new(program.iter().copied());
+                generic.set_overflow_policy(policy);
+                fast.set_overflow_policy(policy);
+                generic.set_run_budget(budget);
+                fast.set_run_budget(budget);
+                generic.extend_inputs(inputs.iter().copied());
+                fast.extend_inputs(inputs.iter().copied());
+                (generic, fast)
+            };
+
+            let (mut generic, mut fast) = new_vms();
+            assert_eq!(
+                format!("{:?}", generic.run_until_input()),
+                format!("{:?}", fast.run_until_input()),
+                "{program:?} {budget:?}"
+            );
+
+            let (mut generic, mut fast) = new_vms();
+            loop {
+                let result = generic.run();
+                assert_eq!(
+                    format!("{result:?}"),
+                    format!("{:?}", fast.run()),
+                    "{program:?}"
+                );
+                assert_eq!(generic.instruction_ptr(), fast.instruction_ptr());
+                assert_eq!(generic.relative_base(), &fast.relative_base());
+                assert!(generic.memory().iter().eq(fast.memory()), "{program:?}");
+                assert_eq!(generic.pending_inputs(), fast.pending_inputs());
+
+                // a budget of 0 never lets the VM go further
+                let resumable = match result {
+                    Ok(VMResult::Output(_)) => true,
+                    Ok(VMResult::BudgetExhausted) => budget != Some(0),
+                    _ => false,
+                };
+                if !resumable {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_async_bounded_channel() {
+        use futures::{channel::mpsc, executor::block_on, future::join3, SinkExt, StreamExt};
+
+        // outputs its input plus one, forever
+        let mut vm = IntcodeVM::new([3, 11, 1001, 11, 1, 11, 4, 11, 1105, 1, 0, 0]);
+        let (mut sender, input) = mpsc::channel(0);
+        let (output, receiver) = mpsc::channel(0);
+
+        let feed = async move {
+            for value in 0..10 {
+                sender.send(value).await.unwrap();
+            }
+        };
+        let (stop, (), outputs) = block_on(join3(
+            vm.run_async(input, output),
+            feed,
+            receiver.collect::<Vec<_>>(),
+        ));
+        assert_eq!(stop.unwrap(), StopReason::WaitingForInput);
+        assert_eq!(outputs, (1..=10).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_run_async_tokio_channels() {
+        use futures::{executor::block_on, future::join};
+        use tokio::sync::mpsc;
+
+        // outputs twice its input, forever
+        let program = [3, 9, 102, 2, 9, 9, 4, 9, 1105, 1, 0];
+        let mut first = IntcodeVM::new(program);
+        let mut second = IntcodeVM::new(program);
+        let (input, first_input) = mpsc::unbounded_channel();
+        let (first_output, second_input) = mpsc::channel(1);
+        let (second_output, mut output) = mpsc::unbounded_channel();
+        input.send(1).unwrap();
+        input.send(5).unwrap();
+        drop(input);
+
+        let (first_stop, second_stop) = block_on(join(
+            first.run_async(first_input, first_output),
+            second.run_async(second_input, second_output),
+        ));
+        assert_eq!(first_stop.unwrap(), StopReason::WaitingForInput);
+        assert_eq!(second_stop.unwrap(), StopReason::WaitingForInput);
+        assert_eq!(output.try_recv(), Ok(4));
+        assert_eq!(output.try_recv(), Ok(20));
+        assert!(output.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_pipeline_errors() {
+        use error::PipelineError;
+        use pipeline::Pipeline;
+
+        // outputs its input plus one
+        let add_one = || IntcodeVM::new([3, 9, 1001, 9, 1, 9, 4, 9, 99]);
+        let mut pipeline = Pipeline::new([add_one(), add_one(), IntcodeVM::new([15])]);
+        assert!(matches!(
+            pipeline.run([1]),
+            Err(PipelineError::Stage {
+                stage: 2,
+                source: error::VMError {
+                    kind: error::VMErrorKind::UnknownInstruction(15),
+                    ..
+                }
+            })
+        ));
+
+        // the second stage reads 2 inputs but the first one only outputs 1
+        let read_two = IntcodeVM::new([3, 0, 3, 0, 99]);
+        let mut pipeline = Pipeline::new([add_one(), read_two, add_one()]);
+        assert!(matches!(
+            pipeline.run([1]),
+            Err(PipelineError::Stalled { stage: 1 })
+        ));
+
+        let mut looping = IntcodeVM::new([1105, 1, 0]);
+        looping.set_run_budget(Some(100));
+        let mut pipeline = Pipeline::new([add_one(), looping]);
+        assert!(matches!(
+            pipeline.run([1]),
+            Err(PipelineError::Stopped {
+                stage: 1,
+                reason: StopReason::BudgetExhausted
+            })
+        ));
+
+        let mut pipeline = Pipeline::new([add_one(), IntcodeVM::new([3, 0, 99])]);
+        assert!(matches!(pipeline.run([1]), Err(PipelineError::NoOutput)));
+
+        let mut pipeline = Pipeline::<i64>::new([]);
+        assert!(matches!(pipeline.run([1]), Err(PipelineError::Empty)));
+
+        let mut pipeline = Pipeline::new([add_one(), add_one(), add_one()]);
+        assert_eq!(pipeline.run([1]).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_network() {
+        use error::NetworkError;
+        use network::{Action, Network, Packet, PacketHandler};
+
+        // machine 0 sends (1, 1) to machine 1, then every machine sends (x + 1, y * 2)
+        // to the next address when it receives (x, y)
+        let program = asm::assemble::<i64>(
+            "
+                    IN -> [address]
+                    JNZ [address], #loop
+                    OUT #1
+                    OUT #1
+                    OUT #1
+            loop:   IN -> [x]
+                    EQ [x], #-1 -> [tmp]
+                    JNZ [tmp], #loop
+                    IN -> [y]
+                    ADD [address], #1 -> [tmp]
+                    OUT [tmp]
+                    ADD [x], #1 -> [x]
+                    OUT [x]
+                    MUL [y], #2 -> [y]
+                    OUT [y]
+                    JNZ #1, #loop
+            address: DATA 0
+            x:      DATA 0
+            y:      DATA 0
+            tmp:    DATA 0
+            ",
+        )
+        .unwrap();
+
+        /// Sends the last packet it received to machine 0 when the network is idle, 3 times
+        #[derive(Default)]
+        struct Nat {
+            received: Vec<Packet<i64>>,
+            last: Option<Packet<i64>>,
+            restarts: usize,
+        }
+
+        impl PacketHandler<i64> for Nat {
+            type Output = i64;
+
+            fn on_packet(&mut self, packet: Packet<i64>) -> Action<i64, i64> {
+                self.received.push(packet);
+                self.last = Some(packet);
+                Action::Continue
+            }
+
+            fn on_idle(&mut self) -> Action<i64, i64> {
+                match self.last.take() {
+                    Some(packet) if self.restarts < 3 => {
+                        self.restarts += 1;
+                        Action::Send(Packet {
+                            destination: 0,
+                            ..packet
+                        })
+                    }
+                    _ => Action::Stop(self.received.len() as i64),
+                }
+            }
+        }
+
+        let mut network = Network::with_program(&program, 4, Nat::default());
+        assert_eq!(network.run().unwrap(), 4);
+        let received = &network.handler().received;
+        assert_eq!(
+            received[0],
+            Packet {
+                source: 3,
+                destination: 4,
+                x: 4,
+                y: 8
+            }
+        );
+        assert_eq!(
+            received.iter().map(|packet| packet.y).collect::<Vec<_>>(),
+            vec![8, 128, 2048, 32768]
+        );
+        assert_eq!(network.machine(2).unwrap().pending_inputs(), 1);
+
+        /// Stops on the first packet
+        struct First;
+
+        impl PacketHandler<i64> for First {
+            type Output = Packet<i64>;
+
+            fn on_packet(&mut self, packet: Packet<i64>) -> Action<i64, Self::Output> {
+                Action::Stop(packet)
+            }
+        }
+
+        let mut network = Network::with_program(&program, 2, First);
+        assert_eq!(
+            network.run().unwrap(),
+            Packet {
+                source: 1,
+                destination: 2,
+                x: 2,
+                y: 2
+            }
+        );
+
+        // reads forever
+        let mut network = Network::new([IntcodeVM::new([3, 5, 1105, 1, 0, 0])], First);
+        assert!(matches!(network.run(), Err(NetworkError::Idle)));
+
+        let mut network = Network::new([IntcodeVM::new([104, -5, 104, 0, 104, 0, 99])], First);
+        assert!(matches!(
+            network.run(),
+            Err(NetworkError::InvalidAddress {
+                machine: 0,
+                address: -5
+            })
+        ));
+
+        let mut network = Network::new(
+            [IntcodeVM::new([3, 0, 99]), IntcodeVM::new([3, 0, 15])],
+            First,
+        );
+        assert!(matches!(
+            network.run(),
+            Err(NetworkError::Machine {
+                machine: 1,
+                source: error::VMError {
+                    kind: error::VMErrorKind::UnknownInstruction(15),
+                    ..
+                }
+            })
+        ));
+
+        let mut network = Network::new([IntcodeVM::new([3, 0, 99])], First);
+        assert!(matches!(network.run(), Err(NetworkError::Halted)));
+    }
+
+    #[test]
+    fn test_fork() {
+        // writes its input at address 5000 and outputs it
+        let memory = memory::Memory::from([3, 5000, 4, 5000, 99]);
+        let mut vm = IntcodeVM::new(memory.into_paged());
+        vm.push_input(1);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+
+        let mut fork = vm.fork();
+        assert_eq!(vm.memory().shared_pages(), 2);
+        assert_eq!(fork.instruction_ptr(), 4);
+
+        fork.memory_mut().set(5000, 2);
+        assert_eq!(vm.memory().shared_pages(), 1);
+        assert_eq!(vm.memory()[5000], 1);
+        assert_eq!(fork.memory()[5000], 2);
+
+        // cloning copies every page
+        let clone = fork.clone();
+        assert_eq!(fork.memory().shared_pages(), 1);
+        assert_eq!(clone.memory().shared_pages(), 0);
+
+        // forking a contiguous memory copies it
+        let dense = IntcodeVM::from([104, 7, 99]);
+        let mut fork = dense.fork();
+        assert!(!fork.memory().is_paged());
+        assert!(!dense.memory().is_paged());
+        assert_eq!(fork.run().unwrap(), VMResult::Output(7));
+    }
+
+    #[test]
+    fn test_watchpoint_read_on_output() {
+        use breakpoint::{BreakReason, WatchKind};
+
+        let mut vm = IntcodeVM::from([4, 5, 104, 1, 99, 42]);
+        vm.breakpoints_mut().add_watchpoint(5, WatchKind::Read);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+        assert_eq!(
+            vm.run().unwrap(),
+            VMResult::Breakpoint {
+                address: 0,
+                reason: BreakReason::Watchpoint {
+                    address: 5,
+                    kind: WatchKind::Read
+                }
+            }
+        );
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    }
+
+    #[test]
+    fn test_watchpoint_change() {
+        use breakpoint::{BreakReason, WatchKind};
+
+        let mut vm = IntcodeVM::from([1101, 0, 0, 9, 1101, 1, 0, 9, 99]);
+        vm.breakpoints_mut().add_watchpoint(9, WatchKind::Change);
+        assert_eq!(
+            vm.run().unwrap(),
+            VMResult::Breakpoint {
+                address: 4,
+                reason: BreakReason::Watchpoint {
+                    address: 9,
+                    kind: WatchKind::Change
+                }
+            }
+        );
+        assert_eq!(vm.instruction_ptr(), 8);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    }
+
+    #[test]
+    fn test_breakpoint_on_waiting_read() {
+        use breakpoint::BreakReason;
+
+        let mut vm = IntcodeVM::from([3, 5, 4, 5, 99]);
+        vm.breakpoints_mut().add_breakpoint(0);
+        let hit = VMResult::Breakpoint {
+            address: 0,
+            reason: BreakReason::Breakpoint,
+        };
+        assert_eq!(vm.run().unwrap(), hit);
+        assert_eq!(vm.run().unwrap(), VMResult::WaitingForInput);
+        vm.push_input(3);
+        assert_eq!(vm.run_until_halt().unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn test_breakpoint_on_halt() {
+        use breakpoint::BreakReason;
+
+        let hit = VMResult::Breakpoint {
+            address: 2,
+            reason: BreakReason::Breakpoint,
+        };
+        let mut vm = IntcodeVM::from([104, 1, 99]);
+        vm.breakpoints_mut().add_breakpoint(2);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+        assert_eq!(vm.run().unwrap(), hit);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+
+        let mut vm = IntcodeVM::from([104, 1, 99]);
+        vm.breakpoints_mut().add_breakpoint(2);
+        assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+        assert_eq!(vm.run().unwrap(), hit);
+        assert!(matches!(vm.step().unwrap(), Step::Executed(executed) if executed.address == 2));
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    }
+
+    #[test]
+    fn test_watchpoint_on_failing_instruction() {
+        use breakpoint::WatchKind;
+
+        // reads the value at 5, then overflows adding 1 to it
+        let memory = memory::Memory::from([1001, 5, 1, 0, 99, i64::MAX]);
+        let mut vm = IntcodeVM::new(memory.clone());
+        vm.breakpoints_mut().add_watchpoint(5, WatchKind::Read);
+        assert!(vm.run().is_err());
+        // the read of the failed instruction is not reported by the next one
+        vm.memory_mut().set(0, 99);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+
+        let mut vm = IntcodeVM::new(memory);
+        vm.breakpoints_mut().add_watchpoint(5, WatchKind::Read);
+        assert!(vm.step().is_err());
+        vm.memory_mut().set(0, 99);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    }
+
+    #[test]
+    fn test_breakpoint_stops_run_until_halt() {
+        use breakpoint::{BreakReason, Condition};
+
+        let mut vm = IntcodeVM::from([104, 1, 109, 5, 104, 2, 99]);
+        let id = vm
+            .breakpoints_mut()
+            .add_condition(Condition::relative_base_greater_than(4));
+        match vm.run_until_halt().map_err(|error| error.kind) {
+            Err(error::VMErrorKind::Stopped { reason, outputs }) => {
+                assert_eq!(
+                    reason,
+                    StopReason::Breakpoint {
+                        address: 4,
+                        reason: BreakReason::Condition(id)
+                    }
+                );
+                assert_eq!(outputs, vec![1]);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        assert_eq!(vm.run_until_halt().unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_profiler() {
+        use instruction::Opcode;
+        use profile::{HotSpot, Loop, Profiler};
+
+        // echoes its inputs until it reads 0
+        let mut vm = IntcodeVM::new([3, 6, 1005, 6, 7, 99, 0, 4, 6, 1105, 1, 0])
+            .with_observer(Profiler::default());
+        assert_eq!(vm.run().unwrap(), VMResult::WaitingForInput);
+        vm.extend_inputs([5, 6]);
+        assert_eq!(
+            vm.run_until_input().unwrap(),
+            (vec![5, 6], StopReason::WaitingForInput)
+        );
+        vm.push_input(0);
+        assert_eq!(vm.run_until_halt().unwrap(), Vec::<i64>::new());
+        // running the halted VM again does not count its halt instruction again
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+        assert_eq!(vm.run_until_halt().unwrap(), Vec::<i64>::new());
+
+        let profiler = vm.observer();
+        assert_eq!(profiler.instructions(), 3 * 2 + 2 * 2 + 1);
+        assert_eq!(profiler.executions(0), 3);
+        assert_eq!(profiler.executions(9), 2);
+        assert_eq!(profiler.executions(5), 1);
+        assert_eq!(profiler.report(4).opcodes[3].opcode, Opcode::Halt);
+        assert_eq!(profiler.report(4).opcodes[3].executions, 1);
+        // the jump condition counts as a read
+        assert_eq!(profiler.reads(6), 3 + 2);
+        assert_eq!(profiler.writes(6), 3);
+
+        let report = profiler.report(2);
+        assert_eq!(report.instructions, 11);
+        assert_eq!(report.opcodes.len(), 4);
+        assert_eq!(report.opcodes[0].opcode, Opcode::JmpIfTrue);
+        assert_eq!(report.opcodes[0].executions, 3 + 2);
+        assert_eq!(
+            report.hot_spots,
+            [
+                HotSpot {
+                    address: 0,
+                    opcode: Opcode::ReadInput,
+                    executions: 3
+                },
+                HotSpot {
+                    address: 2,
+                    opcode: Opcode::JmpIfTrue,
+                    executions: 3
+                }
+            ]
+        );
+        assert_eq!(
+            report.loops,
+            [Loop {
+                start: 0,
+                end: 9,
+                trips: 2,
+                executions: 11
+            }]
+        );
+
+        let display = report.to_string();
+        assert!(display.starts_with("11 instructions executed\n"));
+        assert!(display.contains("  0009 -> 0000: 2 trips, 11 instructions (100.0%)\n"));
+
+        vm.observer_mut().reset();
+        assert_eq!(vm.observer().report(2).instructions, 0);
+    }
+
+    #[test]
+    fn test_watchpoint_read_on_jump_condition() {
+        use breakpoint::{BreakReason, WatchKind};
+
+        let mut vm = IntcodeVM::from([1005, 4, 3, 99, 0]);
+        vm.breakpoints_mut().add_watchpoint(4, WatchKind::Read);
+        assert_eq!(
+            vm.run().unwrap(),
+            VMResult::Breakpoint {
+                address: 0,
+                reason: BreakReason::Watchpoint {
+                    address: 4,
+                    kind: WatchKind::Read
+                }
+            }
+        );
+        assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    }
+
     #[test]
     fn test_16_digits_output() {
         let mut vm: IntcodeVM<i64> = [1102, 34915192, 34915192, 7, 4, 7, 99, 0].into();
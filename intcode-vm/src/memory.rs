@@ -1,7 +1,123 @@
-use std::{ops::Index, str::FromStr};
+use std::{collections::BTreeMap, ops::Index, str::FromStr, sync::Arc};
 
 use num::{Integer, ToPrimitive};
 
+use crate::error::{self, VMErrorKind};
+
+/// Number of values in a page of a [paged](Memory::into_paged) memory
+pub const PAGE_SIZE: usize = 1024;
+
+/// Storage of the values of a [VM](crate::vm::IntcodeVM)
+///
+/// [Memory] is the default implementation, any other one can be used
+/// with [`IntcodeVM::from_backend()`](crate::vm::IntcodeVM::from_backend).
+///
+/// # Example
+///
+/// ```
+/// # use intcode_vm::{error::{self, VMError, VMErrorKind}, memory::{Memory, MemoryBackend}, IntcodeVM};
+/// /// A memory whose first `rom_len` values cannot be overwritten
+/// struct Rom {
+///     memory: Memory<i64>,
+///     rom_len: usize,
+/// }
+///
+/// impl MemoryBackend<i64> for Rom {
+///     fn read(&self, address: usize) -> i64 {
+///         *self.memory.get(address)
+///     }
+///
+///     fn try_set(&mut self, address: usize, value: i64) -> error::Result<(), i64> {
+///         self.check_write(address)?;
+///         self.memory.try_set(address, value)
+///     }
+///
+///     fn check_write(&self, address: usize) -> error::Result<(), i64> {
+///         if address < self.rom_len {
+///             return Err(VMErrorKind::ReadOnlyAddress(address).into());
+///         }
+///
+///         self.memory.check_write(address)
+///     }
+///
+///     fn len(&self) -> usize {
+///         self.memory.len()
+///     }
+/// }
+///
+/// // overwrites its first instruction
+/// let memory = Memory::from([1101, 1, 1, 0, 99]);
+/// let mut vm = IntcodeVM::from_backend(Rom { memory, rom_len: 4 });
+///
+/// assert!(matches!(
+///     vm.run(),
+///     Err(VMError { kind: VMErrorKind::ReadOnlyAddress(0), arg_num: Some(3), .. })
+/// ));
+/// ```
+pub trait MemoryBackend<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    /// Returns the value at `address` (0 if nothing was written there)
+    ///
+    /// It does not have to be stored anywhere: a memory-mapped backend can compute it on every read.
+    /// Unlike [`Memory::get()`], it returns the value itself rather than a reference.
+    fn read(&self, address: usize) -> T;
+
+    /// Replaces the value at `address` with `value`
+    ///
+    /// Must fail (without changing anything) if [`check_write()`](MemoryBackend::check_write) fails.
+    fn try_set(&mut self, address: usize, value: T) -> error::Result<(), T>;
+
+    /// Fails if `address` cannot be written to
+    ///
+    /// The VM calls it before executing an instruction writing to `address`,
+    /// so the instruction does not have any effect (like consuming an input) if it fails.
+    #[inline]
+    fn check_write(&self, address: usize) -> error::Result<(), T> {
+        let _ = address;
+        Ok(())
+    }
+
+    /// Returns the number of values stored in the memory (every address starting from this one is 0)
+    fn len(&self) -> usize;
+
+    /// Returns `true` if no value is stored in the memory
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the first address from `address` onwards where a value is stored,
+    /// [`None`] if every address from `address` is 0
+    ///
+    /// Every address between `address` and the returned one is 0.
+    /// Returns `address` itself by default, a sparse memory can skip what it does not store.
+    #[inline]
+    fn next_stored_address(&self, address: usize) -> Option<usize> {
+        (address < self.len()).then_some(address)
+    }
+
+    /// Creates an [iterator](Iterator) over the values stored in the memory, from address 0
+    ///
+    /// Unlike [`Memory::iter()`], it yields the values themselves rather than references.
+    #[inline]
+    fn values(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len()).map(|address| self.read(address))
+    }
+
+    /// Creates a copy of the memory for a [forked](crate::vm::IntcodeVM::fork) VM
+    ///
+    /// Clones the memory by default, implementations can share what they can with the copy instead.
+    #[inline]
+    fn fork(&self) -> Self
+    where
+        Self: Sized + Clone,
+    {
+        self.clone()
+    }
+}
+
 /// Represents a [VM's](crate::vm::IntcodeVM) memory
 ///
 /// # Example
@@ -14,13 +130,60 @@ use num::{Integer, ToPrimitive};
 /// // do something with it before passing it to the VM
 /// let vm = IntcodeVM::new(memory);
 /// ```
+///
+/// By default the values are stored contiguously, so writing to a far away address
+/// allocates every address before it. A [paged](Memory::into_paged) memory only allocates
+/// the [pages](PAGE_SIZE) that are written to:
+/// ```
+/// # use intcode_vm::{memory::Memory, IntcodeVM, VMResult};
+/// // writes 42 at address 10^12 then outputs it
+/// let memory = Memory::from([1101, 40, 2, 1_000_000_000_000_i64, 4, 1_000_000_000_000, 99]);
+/// let mut vm = IntcodeVM::new(memory.into_paged());
+///
+/// assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+/// ```
 #[derive(Debug, Clone)]
 pub struct Memory<T>
 where
     T: Integer + Clone + ToPrimitive,
 {
     zero: T,
-    mem: Vec<T>,
+    storage: Storage<T>,
+    limit: Option<usize>,
+}
+
+/// How the values of a [Memory] are stored
+#[derive(Debug)]
+enum Storage<T> {
+    /// Every value from address 0
+    Dense(Vec<T>),
+    /// Pages of [PAGE_SIZE] values, by page number
+    ///
+    /// The pages can be shared with [forks](Memory::fork), they are copied when written to.
+    Paged {
+        pages: BTreeMap<usize, Arc<[T]>>,
+        /// One past the highest address written to
+        len: usize,
+    },
+}
+
+impl<T> Clone for Storage<T>
+where
+    T: Clone,
+{
+    /// Copies every page, even those shared with forks
+    fn clone(&self) -> Self {
+        match self {
+            Self::Dense(mem) => Self::Dense(mem.clone()),
+            Self::Paged { pages, len } => Self::Paged {
+                pages: pages
+                    .iter()
+                    .map(|(&number, page)| (number, Arc::from(&page[..])))
+                    .collect(),
+                len: *len,
+            },
+        }
+    }
 }
 
 impl<T> Memory<T>
@@ -61,7 +224,13 @@ where
     /// ```
     #[inline]
     pub fn get(&self, address: usize) -> &T {
-        self.mem.get(address).unwrap_or(&self.zero)
+        match &self.storage {
+            Storage::Dense(mem) => mem.get(address),
+            Storage::Paged { pages, .. } => pages
+                .get(&(address / PAGE_SIZE))
+                .map(|page| &page[address % PAGE_SIZE]),
+        }
+        .unwrap_or(&self.zero)
     }
 
     /// Replaces the value at `address` with `value`
@@ -91,13 +260,292 @@ where
     /// assert_eq!(memory.get(10), &2);
     /// assert!(memory.memory_starts_with(&[1, 0, 0, 3, 99, 0, 0, 0, 0, 0, 2]));
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `address` is not below the [limit](Memory::set_limit) of the memory
+    /// (see [`memory.try_set()`](Memory::try_set))
     #[inline]
     pub fn set(&mut self, address: usize, value: T) {
-        if let Some(existing) = self.mem.get_mut(address) {
-            *existing = value;
-        } else {
-            self.mem.resize(address, self.zero.clone());
-            self.mem.push(value);
+        if let Err(error) = self.try_set(address, value) {
+            if let VMErrorKind::MemoryLimitExceeded { address, limit } = error.kind {
+                panic!(
+                    "Cannot write to address {}, the memory is limited to {} values",
+                    address, limit
+                );
+            }
+        }
+    }
+
+    /// Same as [`memory.set()`](Memory::set), but fails with [`VMErrorKind::MemoryLimitExceeded`]
+    /// instead of panicking if `address` is not below the [limit](Memory::set_limit) of the memory
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{error::VMErrorKind, memory::Memory};
+    /// let mut memory = Memory::from([1, 0, 0, 3, 99]);
+    /// memory.set_limit(Some(100));
+    ///
+    /// assert!(memory.try_set(99, 1).is_ok());
+    /// assert!(matches!(
+    ///     memory.try_set(100, 1).map_err(|error| error.kind),
+    ///     Err(VMErrorKind::MemoryLimitExceeded { address: 100, limit: 100 })
+    /// ));
+    /// ```
+    pub fn try_set(&mut self, address: usize, value: T) -> error::Result<(), T> {
+        self.check_write(address)?;
+
+        match &mut self.storage {
+            Storage::Dense(mem) => {
+                if let Some(existing) = mem.get_mut(address) {
+                    *existing = value;
+                } else {
+                    mem.resize(address, self.zero.clone());
+                    mem.push(value);
+                }
+            }
+            Storage::Paged { pages, len } => {
+                let page = pages
+                    .entry(address / PAGE_SIZE)
+                    .or_insert_with(|| vec![self.zero.clone(); PAGE_SIZE].into());
+                Arc::make_mut(page)[address % PAGE_SIZE] = value;
+                // check_write() rejects usize::MAX, so this cannot overflow
+                *len = (*len).max(address + 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [`VMErrorKind::MemoryLimitExceeded`] if `address` is not below the [limit](Memory::set_limit)
+    ///
+    /// Without a limit, only `usize::MAX` is rejected, as the length of the memory would not fit in a [usize].
+    #[inline]
+    pub fn check_write(&self, address: usize) -> error::Result<(), T> {
+        match self.limit {
+            Some(limit) if address >= limit => {
+                Err(VMErrorKind::MemoryLimitExceeded { address, limit }.into())
+            }
+            None if address.checked_add(1).is_none() => Err(VMErrorKind::MemoryLimitExceeded {
+                address,
+                limit: usize::MAX,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Forbids writing to any address from `limit` onwards, [`None`] to remove the limit
+    ///
+    /// The VM returns [`VMErrorKind::MemoryLimitExceeded`] when an instruction tries to,
+    /// instead of allocating as much memory as the address requires.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{error::VMErrorKind, memory::Memory, IntcodeVM};
+    /// // writes 42 at address 10^12
+    /// let mut memory = Memory::from([1101, 40, 2, 1_000_000_000_000_i64, 99]);
+    /// memory.set_limit(Some(1 << 20));
+    /// let mut vm = IntcodeVM::new(memory);
+    ///
+    /// assert!(matches!(
+    ///     vm.run().map_err(|error| error.kind),
+    ///     Err(VMErrorKind::MemoryLimitExceeded { address: 1_000_000_000_000, limit: 1_048_576 })
+    /// ));
+    /// ```
+    #[inline]
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// Returns the limit set by [`memory.set_limit()`](Memory::set_limit), if any
+    #[inline]
+    pub const fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Converts the memory to a paged memory, which only allocates the pages of
+    /// [PAGE_SIZE] values that are written to
+    ///
+    /// Reading and writing values is slower than with the default contiguous memory,
+    /// but any address can be written to without allocating every address before it.
+    #[inline]
+    pub fn into_paged(self) -> Self {
+        if self.is_paged() {
+            return self;
+        }
+
+        let mut paged = Self {
+            zero: self.zero.clone(),
+            storage: Storage::Paged {
+                pages: BTreeMap::new(),
+                len: 0,
+            },
+            limit: None,
+        };
+        for (address, value) in self.iter().enumerate() {
+            paged.set(address, value.clone());
+        }
+
+        paged.limit = self.limit;
+        paged
+    }
+
+    /// Creates a copy of the memory sharing its pages with this one,
+    /// each of them only copies a page when it writes to it
+    ///
+    /// Only paged memories can share pages: a contiguous memory is simply cloned, which is
+    /// the cheapest for small programs (reading a paged memory is slower than a contiguous one).
+    /// [Convert](Memory::into_paged) a large memory once beforehand so its forks share its pages.
+    /// [`memory.clone()`](Clone::clone) always copies the whole memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::memory::Memory;
+    /// let memory = Memory::from([1, 0, 0, 3, 99]).into_paged();
+    /// let mut fork = memory.fork();
+    /// fork.set(3, 7);
+    ///
+    /// assert_eq!(memory[3], 3);
+    /// assert_eq!(fork[3], 7);
+    /// ```
+    pub fn fork(&self) -> Self {
+        match &self.storage {
+            Storage::Dense(_) => self.clone(),
+            Storage::Paged { pages, len } => Self {
+                zero: self.zero.clone(),
+                storage: Storage::Paged {
+                    pages: pages.clone(),
+                    len: *len,
+                },
+                limit: self.limit,
+            },
+        }
+    }
+
+    /// Returns the number of pages of this memory shared with [forks](Memory::fork)
+    #[cfg(test)]
+    pub(crate) fn shared_pages(&self) -> usize {
+        match &self.storage {
+            Storage::Dense(_) => 0,
+            Storage::Paged { pages, .. } => pages
+                .values()
+                .filter(|page| Arc::strong_count(page) > 1)
+                .count(),
+        }
+    }
+
+    /// Returns `true` if the memory is [paged](Memory::into_paged)
+    #[inline]
+    pub const fn is_paged(&self) -> bool {
+        matches!(self.storage, Storage::Paged { .. })
+    }
+
+    /// Creates a memory from its [segments](Memory::segments)
+    ///
+    /// The limit is set once every value is written, as it may have been lowered below
+    /// the length of the memory. Fails with [`VMErrorKind::MemoryLimitExceeded`] if a segment
+    /// does not fit in the address space.
+    pub(crate) fn from_segments<I>(
+        segments: I,
+        paged: bool,
+        limit: Option<usize>,
+    ) -> error::Result<Self, T>
+    where
+        I: IntoIterator<Item = (usize, Vec<T>)>,
+    {
+        let mut memory = Self::from_iter(std::iter::empty());
+        if paged {
+            memory = memory.into_paged();
+        }
+
+        for (start, values) in segments {
+            for (offset, value) in values.into_iter().enumerate() {
+                let address =
+                    start
+                        .checked_add(offset)
+                        .ok_or(VMErrorKind::MemoryLimitExceeded {
+                            address: usize::MAX,
+                            limit: usize::MAX,
+                        })?;
+                memory.try_set(address, value)?;
+            }
+        }
+
+        memory.limit = limit;
+        Ok(memory)
+    }
+
+    /// Returns the allocated parts of the memory, as their first address and their values
+    ///
+    /// Every address that is not in one of them is 0. They end at the [length](Memory::len)
+    /// of the memory, so [`Memory::from_segments()`] gives back the same length.
+    pub(crate) fn segments(&self) -> Box<dyn Iterator<Item = (usize, &[T])> + '_> {
+        match &self.storage {
+            Storage::Dense(mem) => Box::new(std::iter::once((0, mem.as_slice()))),
+            Storage::Paged { pages, len } => Box::new(pages.iter().map(|(page, values)| {
+                let start = page * PAGE_SIZE;
+                (start, &values[..len.saturating_sub(start).min(PAGE_SIZE)])
+            })),
+        }
+    }
+
+    /// Returns the number of values stored in the memory
+    ///
+    /// Every address starting from this one is 0
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::memory::Memory;
+    /// let mut memory = Memory::from([1, 0, 0, 3, 99]);
+    /// assert_eq!(memory.len(), 5);
+    ///
+    /// memory.set(10, 2);
+    /// assert_eq!(memory.len(), 11);
+    /// ```
+    #[inline]
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Dense(mem) => mem.len(),
+            Storage::Paged { len, .. } => *len,
+        }
+    }
+
+    /// Returns `true` if no value is stored in the memory
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the first address from `address` onwards where a value is stored,
+    /// [`None`] if every address from `address` is 0
+    ///
+    /// A [paged](Memory::into_paged) memory skips the pages that were never written to.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::memory::{Memory, PAGE_SIZE};
+    /// let mut memory = Memory::from([1, 0, 0, 3, 99]).into_paged();
+    /// memory.set(10 * PAGE_SIZE + 1, 42);
+    ///
+    /// assert_eq!(memory.next_stored_address(3), Some(3));
+    /// assert_eq!(memory.next_stored_address(5), Some(5));
+    /// assert_eq!(memory.next_stored_address(PAGE_SIZE), Some(10 * PAGE_SIZE));
+    /// assert_eq!(memory.next_stored_address(10 * PAGE_SIZE + 2), None);
+    /// ```
+    pub fn next_stored_address(&self, address: usize) -> Option<usize> {
+        match &self.storage {
+            Storage::Dense(mem) => Some(address).filter(|&address| address < mem.len()),
+            Storage::Paged { pages, len } => pages
+                .range(address / PAGE_SIZE..)
+                .next()
+                .map(|(&page, _)| address.max(page * PAGE_SIZE))
+                .filter(|&address| address < *len),
         }
     }
 
@@ -119,7 +567,7 @@ where
     /// ```
     #[inline]
     pub fn iter(&self) -> impl Iterator<Item = &T> {
-        self.mem.iter()
+        (0..self.len()).map(|address| self.get(address))
     }
 
     /// Checks if this memory's first `n` elements are the same as the `n` elements of `iter`
@@ -159,6 +607,46 @@ where
     }
 }
 
+impl<T> MemoryBackend<T> for Memory<T>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    #[inline]
+    fn read(&self, address: usize) -> T {
+        Memory::get(self, address).clone()
+    }
+
+    #[inline]
+    fn try_set(&mut self, address: usize, value: T) -> error::Result<(), T> {
+        Memory::try_set(self, address, value)
+    }
+
+    #[inline]
+    fn check_write(&self, address: usize) -> error::Result<(), T> {
+        Memory::check_write(self, address)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        Memory::len(self)
+    }
+
+    #[inline]
+    fn next_stored_address(&self, address: usize) -> Option<usize> {
+        Memory::next_stored_address(self, address)
+    }
+
+    #[inline]
+    fn values(&self) -> impl Iterator<Item = T> + '_ {
+        Memory::iter(self).cloned()
+    }
+
+    #[inline]
+    fn fork(&self) -> Self {
+        Memory::fork(self)
+    }
+}
+
 impl<T> Index<usize> for Memory<T>
 where
     T: Integer + Clone + ToPrimitive,
@@ -178,7 +666,8 @@ where
     fn from_iter<IT: IntoIterator<Item = T>>(iter: IT) -> Self {
         Self {
             zero: T::zero(),
-            mem: iter.into_iter().collect(),
+            storage: Storage::Dense(iter.into_iter().collect()),
+            limit: None,
         }
     }
 }
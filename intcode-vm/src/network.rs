@@ -0,0 +1,332 @@
+//! Networks of [VMs](crate::vm::IntcodeVM) sending packets to each other
+//!
+//! Every machine of a [Network] first reads its address (from 0 to N - 1),
+//! then sends packets by outputting 3 values: the address of the destination, `x` and `y`.
+//! Packets are queued for their destination, which reads `x` then `y` when it asks for an input,
+//! or `-1` if its queue is empty (so values have to be [signed](num::Signed)).
+//!
+//! Packets sent to any other address are given to the [PacketHandler] of the network,
+//! which is also told when the network becomes idle.
+//! Machines are run one after the other on the current thread, so a network always behaves the same.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{asm, network::{Action, Network, Packet, PacketHandler}};
+//! // sends 100 + its address to the NAT, then waits forever
+//! let program = asm::assemble::<i64>(
+//!     "
+//!             IN -> [address]
+//!             ADD [address], #100 -> [address]
+//!             OUT #255
+//!             OUT #0
+//!             OUT [address]
+//!     loop:   IN -> [address]
+//!             JNZ #1, #loop
+//!     address: DATA 0
+//!     ",
+//! )
+//! .unwrap();
+//!
+//! /// Keeps the last packet it received, returns its `y` once the network is idle
+//! struct Nat(Option<i64>);
+//!
+//! impl PacketHandler<i64> for Nat {
+//!     type Output = Option<i64>;
+//!
+//!     fn on_packet(&mut self, packet: Packet<i64>) -> Action<i64, Self::Output> {
+//!         self.0 = Some(packet.y);
+//!         Action::Continue
+//!     }
+//!
+//!     fn on_idle(&mut self) -> Action<i64, Self::Output> {
+//!         Action::Stop(self.0)
+//!     }
+//! }
+//!
+//! let mut network = Network::with_program(&program, 5, Nat(None));
+//! assert_eq!(network.run().unwrap(), Some(104));
+//! ```
+
+use std::{collections::VecDeque, mem};
+
+use num::{Integer, Signed, ToPrimitive};
+
+use crate::{
+    arithmetic::OverflowArithmetic,
+    error::NetworkError,
+    memory::{Memory, MemoryBackend},
+    observer::{NoopObserver, Observer},
+    vm::{IntcodeVM, StopReason, VMResult},
+};
+
+/// Number of times in a row a machine must read `-1` before it is considered idle
+const IDLE_READS: u32 = 2;
+
+/// A packet sent by a machine of a [Network]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Packet<T> {
+    /// Address of the machine which sent the packet
+    pub source: usize,
+    /// Address the packet is sent to
+    pub destination: usize,
+    pub x: T,
+    pub y: T,
+}
+
+/// What a [Network] should do after calling its [PacketHandler]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action<T, R> {
+    /// Keep running the machines
+    Continue,
+    /// Queue the packet for the machine at its destination, then keep running
+    Send(Packet<T>),
+    /// Stop the network, [`network.run()`](Network::run) returns this value
+    Stop(R),
+}
+
+/// Handles what the machines of a [Network] cannot: packets sent to addresses that are not machines
+/// and the network being idle
+///
+/// See the [module documentation](crate::network) for an example.
+pub trait PacketHandler<T> {
+    /// Value returned by [`network.run()`](Network::run) when the handler [stops](Action::Stop) it
+    type Output;
+
+    /// Called with every packet sent to an address that is not a machine of the network
+    fn on_packet(&mut self, packet: Packet<T>) -> Action<T, Self::Output>;
+
+    /// Called when the network is idle: no packet is waiting to be read
+    /// and every machine read `-1` several times in a row without sending anything
+    ///
+    /// [`network.run()`](Network::run) fails with [`NetworkError::Idle`] if this returns [`Action::Continue`],
+    /// since the network would stay idle forever.
+    #[inline]
+    fn on_idle(&mut self) -> Action<T, Self::Output> {
+        Action::Continue
+    }
+}
+
+/// A machine of a [Network], with what the network keeps for it
+#[derive(Debug, Clone)]
+struct Machine<T, O, M>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    vm: IntcodeVM<T, O, M>,
+    /// `x` and `y` of the packets waiting to be read
+    queue: VecDeque<(T, T)>,
+    /// Values output since the last complete packet
+    output: Vec<T>,
+    /// Number of times in a row the machine read `-1`
+    empty_reads: u32,
+    halted: bool,
+}
+
+/// VMs sending packets to each other, see the [module documentation](crate::network)
+#[derive(Debug, Clone)]
+pub struct Network<T, H, O = NoopObserver, M = Memory<T>>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    machines: Vec<Machine<T, O, M>>,
+    handler: H,
+}
+
+impl<T, H> Network<T, H>
+where
+    T: Integer + Signed + Clone + ToPrimitive + OverflowArithmetic,
+    H: PacketHandler<T>,
+{
+    /// Creates a network of `size` machines running `program`
+    pub fn with_program(program: &Memory<T>, size: usize, handler: H) -> Self {
+        Self::new((0..size).map(|_| IntcodeVM::new(program.clone())), handler)
+    }
+}
+
+impl<T, H, O, M> Network<T, H, O, M>
+where
+    T: Integer + Signed + Clone + ToPrimitive + OverflowArithmetic,
+    H: PacketHandler<T>,
+    O: Observer<T>,
+    M: MemoryBackend<T>,
+{
+    /// Creates a network from its machines, giving each one its address (its index) as its first input
+    pub fn new<I: IntoIterator<Item = IntcodeVM<T, O, M>>>(machines: I, handler: H) -> Self {
+        let mut address = T::zero();
+        let machines = machines
+            .into_iter()
+            .map(|mut vm| {
+                vm.push_input(address.clone());
+                address = address.clone() + T::one();
+                Machine {
+                    vm,
+                    queue: VecDeque::new(),
+                    output: Vec::with_capacity(3),
+                    empty_reads: 0,
+                    halted: false,
+                }
+            })
+            .collect();
+
+        Self { machines, handler }
+    }
+
+    /// Returns the VM of the machine at `address`, if there is one
+    #[inline]
+    pub fn machine(&self, address: usize) -> Option<&IntcodeVM<T, O, M>> {
+        self.machines.get(address).map(|machine| &machine.vm)
+    }
+
+    /// Returns the [PacketHandler] of the network
+    #[inline]
+    pub const fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Runs the machines one after the other, each one until it reads an input,
+    /// until the [PacketHandler] [stops](Action::Stop) the network
+    ///
+    /// Fails with a [NetworkError] if a machine fails, stops for another reason than halting
+    /// (breakpoint, budget) or sends a packet to an invalid address,
+    /// if the network is idle and the handler does not send anything,
+    /// or if every machine halted.
+    pub fn run(&mut self) -> Result<H::Output, NetworkError<T>> {
+        loop {
+            for address in 0..self.machines.len() {
+                if let Some(output) = self.run_machine(address)? {
+                    return Ok(output);
+                }
+            }
+
+            if self.machines.iter().all(|machine| machine.halted) {
+                return Err(NetworkError::Halted);
+            }
+
+            if self.is_idle() {
+                match self.handler.on_idle() {
+                    Action::Continue => return Err(NetworkError::Idle),
+                    Action::Send(packet) => self.deliver(packet)?,
+                    Action::Stop(output) => return Ok(output),
+                }
+            }
+        }
+    }
+
+    /// Runs the machine at `address` until it reads an input (or halts), routing the packets it sends
+    fn run_machine(&mut self, address: usize) -> Result<Option<H::Output>, NetworkError<T>> {
+        if self.machines[address].halted {
+            return Ok(None);
+        }
+
+        loop {
+            let machine = &mut self.machines[address];
+            let result = machine.vm.run().map_err(|source| NetworkError::Machine {
+                machine: address,
+                source,
+            })?;
+
+            match result {
+                VMResult::Output(value) => {
+                    machine.empty_reads = 0;
+                    machine.output.push(value);
+                    if machine.output.len() < 3 {
+                        continue;
+                    }
+
+                    let output = mem::replace(&mut machine.output, Vec::with_capacity(3));
+                    if let Ok([destination, x, y]) = <[T; 3]>::try_from(output) {
+                        if let Some(output) = self.route(address, destination, x, y)? {
+                            return Ok(Some(output));
+                        }
+                    }
+                }
+                VMResult::WaitingForInput => {
+                    if let Some((x, y)) = machine.queue.pop_front() {
+                        machine.empty_reads = 0;
+                        machine.vm.extend_inputs([x, y]);
+                    } else {
+                        machine.empty_reads += 1;
+                        machine.vm.push_input(-T::one());
+                    }
+                    return Ok(None);
+                }
+                VMResult::Halted => {
+                    machine.halted = true;
+                    return Ok(None);
+                }
+                VMResult::Breakpoint {
+                    address: breakpoint,
+                    reason,
+                } => {
+                    return Err(NetworkError::Stopped {
+                        machine: address,
+                        reason: StopReason::Breakpoint {
+                            address: breakpoint,
+                            reason,
+                        },
+                    })
+                }
+                VMResult::BudgetExhausted => {
+                    return Err(NetworkError::Stopped {
+                        machine: address,
+                        reason: StopReason::BudgetExhausted,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Sends the packet output by the machine at `source` to its destination
+    fn route(
+        &mut self,
+        source: usize,
+        destination: T,
+        x: T,
+        y: T,
+    ) -> Result<Option<H::Output>, NetworkError<T>> {
+        let Some(destination) = destination.to_usize() else {
+            return Err(NetworkError::InvalidAddress {
+                machine: source,
+                address: destination,
+            });
+        };
+
+        let packet = Packet {
+            source,
+            destination,
+            x,
+            y,
+        };
+        if destination < self.machines.len() {
+            self.deliver(packet)?;
+            return Ok(None);
+        }
+
+        match self.handler.on_packet(packet) {
+            Action::Continue => Ok(None),
+            Action::Send(packet) => self.deliver(packet).map(|()| None),
+            Action::Stop(output) => Ok(Some(output)),
+        }
+    }
+
+    /// Queues `packet` for the machine at its destination
+    fn deliver(&mut self, packet: Packet<T>) -> Result<(), NetworkError<T>> {
+        let machine = self
+            .machines
+            .get_mut(packet.destination)
+            .ok_or(NetworkError::UnknownDestination(packet.destination))?;
+        machine.queue.push_back((packet.x, packet.y));
+        Ok(())
+    }
+
+    /// Returns `true` if no packet is waiting to be read and every running machine is idle
+    fn is_idle(&self) -> bool {
+        self.machines.iter().all(|machine| {
+            machine.halted
+                || (machine.queue.is_empty()
+                    && machine.output.is_empty()
+                    && machine.empty_reads >= IDLE_READS)
+        })
+    }
+}
@@ -0,0 +1,80 @@
+//! Hooks called by the [VM](crate::vm::IntcodeVM) while it executes a program
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{instruction::Opcode, observer::Observer, IntcodeVM};
+//! #[derive(Default)]
+//! struct WriteCounter {
+//!     instructions: usize,
+//!     writes: usize,
+//! }
+//!
+//! impl<T> Observer<T> for WriteCounter {
+//!     fn before_instruction(&mut self, _address: usize, _opcode: Opcode) {
+//!         self.instructions += 1;
+//!     }
+//!
+//!     fn on_memory_write(&mut self, _address: usize, _old: &T, _new: &T) {
+//!         self.writes += 1;
+//!     }
+//! }
+//!
+//! let mut vm = IntcodeVM::new([1, 0, 0, 3, 2, 3, 3, 0, 99]).with_observer(WriteCounter::default());
+//! vm.run().unwrap();
+//!
+//! assert_eq!(vm.observer().instructions, 3);
+//! assert_eq!(vm.observer().writes, 2);
+//! ```
+
+use crate::instruction::Opcode;
+
+/// Callbacks invoked by a [VM](crate::vm::IntcodeVM) during execution
+///
+/// Every method does nothing by default, so implementors only need to override the ones they need.
+pub trait Observer<T> {
+    /// Called before the instruction at `address` is executed
+    ///
+    /// Called once per instruction: a read instruction waiting for an input is not reported again
+    /// once the VM is resumed, and neither is the halt instruction of a VM that is run again.
+    #[inline]
+    fn before_instruction(&mut self, address: usize, opcode: Opcode) {
+        let _ = (address, opcode);
+    }
+
+    /// Called when an instruction reads `value` from `address`
+    #[inline]
+    fn on_memory_read(&mut self, address: usize, value: &T) {
+        let _ = (address, value);
+    }
+
+    /// Called before the value at `address` is replaced by `new`
+    #[inline]
+    fn on_memory_write(&mut self, address: usize, old: &T, new: &T) {
+        let _ = (address, old, new);
+    }
+
+    /// Called before the relative base is changed from `old` to `new`
+    #[inline]
+    fn on_relative_base_change(&mut self, old: &T, new: &T) {
+        let _ = (old, new);
+    }
+
+    /// Called when a read instruction consumes `value`
+    #[inline]
+    fn on_input(&mut self, value: &T) {
+        let _ = value;
+    }
+
+    /// Called when a write instruction outputs `value`
+    #[inline]
+    fn on_output(&mut self, value: &T) {
+        let _ = value;
+    }
+}
+
+/// The default [Observer] of a [VM](crate::vm::IntcodeVM), does nothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NoopObserver;
+
+impl<T> Observer<T> for NoopObserver {}
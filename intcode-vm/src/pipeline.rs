@@ -0,0 +1,162 @@
+//! Chains of [VMs](crate::vm::IntcodeVM), each one reading the outputs of the previous one
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{memory::Memory, pipeline::Pipeline};
+//! // outputs its second input times 10 plus its first input
+//! let program = Memory::from([3, 20, 3, 21, 1002, 21, 10, 21, 1, 20, 21, 21, 4, 21, 99]);
+//!
+//! // each stage starts with its digit, then reads the output of the previous one
+//! let mut pipeline = Pipeline::with_initial_inputs(&program, [1, 2, 3]);
+//! assert_eq!(pipeline.run([0]).unwrap(), 123);
+//! ```
+//!
+//! With a feedback edge, the outputs of the last stage go back to the first one
+//! until every stage halts:
+//!
+//! ```
+//! # use intcode_vm::{memory::Memory, pipeline::Pipeline};
+//! // adds its first input to each of the next 3, outputting the sums
+//! let program = Memory::from([
+//!     3, 30, 1101, 3, 0, 31, 3, 32, 1, 30, 32, 32, 4, 32, 1001, 31, -1, 31, 1005, 31, 6, 99,
+//! ]);
+//!
+//! let mut pipeline = Pipeline::with_initial_inputs(&program, [1, 10]).with_feedback();
+//! // 0 -> 1 -> 11 -> 12 -> 22 -> 23 -> 33
+//! assert_eq!(pipeline.run([0]).unwrap(), 33);
+//! ```
+
+use num::{Integer, ToPrimitive};
+
+use crate::{
+    arithmetic::OverflowArithmetic,
+    error::PipelineError,
+    memory::{Memory, MemoryBackend},
+    observer::{NoopObserver, Observer},
+    vm::{IntcodeVM, StopReason},
+};
+
+/// VMs connected output to input, optionally with the last one connected to the first one
+///
+/// See the [module documentation](crate::pipeline) for examples.
+#[derive(Debug, Clone)]
+pub struct Pipeline<T, O = NoopObserver, M = Memory<T>>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    stages: Vec<IntcodeVM<T, O, M>>,
+    feedback: bool,
+}
+
+impl<T> Pipeline<T>
+where
+    T: Integer + Clone + ToPrimitive + OverflowArithmetic,
+{
+    /// Creates a pipeline with one VM running `program` per value of `initial_inputs`,
+    /// each VM starting with its value in its input queue (e.g. a phase setting)
+    pub fn with_initial_inputs<I>(program: &Memory<T>, initial_inputs: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Self::new(initial_inputs.into_iter().map(|input| {
+            let mut vm = IntcodeVM::new(program.clone());
+            vm.push_input(input);
+            vm
+        }))
+    }
+}
+
+impl<T, O, M> Pipeline<T, O, M>
+where
+    T: Integer + Clone + ToPrimitive + OverflowArithmetic,
+    O: Observer<T>,
+    M: MemoryBackend<T>,
+{
+    /// Creates a pipeline from its stages, in order, without feedback edge
+    #[inline]
+    pub fn new<I: IntoIterator<Item = IntcodeVM<T, O, M>>>(stages: I) -> Self {
+        Self {
+            stages: stages.into_iter().collect(),
+            feedback: false,
+        }
+    }
+
+    /// Connects the output of the last stage to the input of the first one
+    #[inline]
+    pub fn with_feedback(mut self) -> Self {
+        self.feedback = true;
+        self
+    }
+
+    /// Returns the stages of the pipeline, in order
+    #[inline]
+    pub fn stages(&self) -> &[IntcodeVM<T, O, M>] {
+        &self.stages
+    }
+
+    /// Returns the stages of the pipeline, in order, to change their inputs, budgets, etc.
+    #[inline]
+    pub fn stages_mut(&mut self) -> &mut [IntcodeVM<T, O, M>] {
+        &mut self.stages
+    }
+
+    /// Gives `inputs` to the first stage and runs every stage until they all halt
+    ///
+    /// Returns the last value output by the last stage (with a feedback edge, it is also
+    /// given to the first stage, which has halted by then and never reads it).
+    ///
+    /// Fails with a [PipelineError] naming the stage that misbehaved if a VM fails,
+    /// stops for another reason than halting (breakpoint, budget)
+    /// or waits for an input that no stage will output.
+    pub fn run<I>(&mut self, inputs: I) -> Result<T, PipelineError<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let stage_count = self.stages.len();
+        let first = self.stages.first_mut().ok_or(PipelineError::Empty)?;
+        first.extend_inputs(inputs);
+
+        let mut halted = vec![false; stage_count];
+        let mut last_output = None;
+        while halted.contains(&false) {
+            let mut progressed = false;
+            for (stage, halted) in halted.iter_mut().enumerate() {
+                if *halted {
+                    continue;
+                }
+
+                let vm = &mut self.stages[stage];
+                let executed = vm.executed_instructions();
+                let (outputs, reason) = vm
+                    .run_until_input()
+                    .map_err(|source| PipelineError::Stage { stage, source })?;
+                progressed |= vm.executed_instructions() != executed;
+
+                match reason {
+                    StopReason::Halted => {
+                        *halted = true;
+                        progressed = true;
+                    }
+                    StopReason::WaitingForInput => (),
+                    reason => return Err(PipelineError::Stopped { stage, reason }),
+                }
+
+                if stage + 1 == stage_count {
+                    last_output = outputs.last().cloned().or(last_output);
+                }
+
+                if stage + 1 < stage_count || self.feedback {
+                    self.stages[(stage + 1) % stage_count].extend_inputs(outputs);
+                }
+            }
+
+            if !progressed {
+                let stage = halted.iter().position(|&halted| !halted).unwrap_or(0);
+                return Err(PipelineError::Stalled { stage });
+            }
+        }
+
+        last_output.ok_or(PipelineError::NoOutput)
+    }
+}
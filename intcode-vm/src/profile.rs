@@ -0,0 +1,327 @@
+//! Where a [VM](crate::vm::IntcodeVM) spends its time
+//!
+//! [Profiler] is an [Observer] counting how many times each instruction is executed
+//! (per address and per opcode), how many times each address is read and written,
+//! and how many times each backward jump is taken: every one of them closes a loop,
+//! and the number of times it is taken is the number of trips around that loop.
+//!
+//! Its [report](Profiler::report) keeps the hottest of them. It is printed in a human readable form
+//! with [Display](fmt::Display), and as JSON with [`report.to_json()`](ProfileReport::to_json)
+//! (requires the `json` feature, the `serde` feature alone only makes the report `Serialize`).
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{asm, instruction::Opcode, profile::Profiler, IntcodeVM};
+//! // outputs 3 + 2 + 1
+//! let program = asm::assemble::<i64>(
+//!     "
+//!     loop:   ADD [sum], [count] -> [sum]
+//!             ADD [count], #-1 -> [count]
+//!             JNZ [count], #loop
+//!             OUT [sum]
+//!             HLT
+//!     sum:    DATA 0
+//!     count:  DATA 3
+//!     ",
+//! )
+//! .unwrap();
+//!
+//! let mut vm = IntcodeVM::new(program).with_observer(Profiler::default());
+//! assert_eq!(vm.run_until_halt().unwrap(), vec![6]);
+//!
+//! let report = vm.observer().report(3);
+//! assert_eq!(report.instructions, 11);
+//! assert_eq!(report.hot_spots[0].address, 0);
+//! assert_eq!(report.hot_spots[0].executions, 3);
+//! assert_eq!(report.opcodes[0].opcode, Opcode::Add);
+//! assert_eq!(report.opcodes[0].executions, 6);
+//! assert_eq!((report.loops[0].start, report.loops[0].end), (0, 8));
+//! assert_eq!(report.loops[0].trips, 2);
+//! assert_eq!(report.reads[0].address, 15);
+//! assert_eq!(report.reads[0].count, 9);
+//!
+//! println!("{report}");
+//! ```
+
+use std::{collections::HashMap, fmt, hash::Hash};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{instruction::Opcode, observer::Observer};
+
+/// [Observer] counting what the VM executes, reads and writes,
+/// see the [module documentation](crate::profile)
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    /// Number of executions and opcode of the instruction at each address
+    executions: HashMap<usize, (Opcode, u64)>,
+    opcodes: HashMap<Opcode, u64>,
+    reads: HashMap<usize, u64>,
+    writes: HashMap<usize, u64>,
+    /// Number of times each backward jump was taken, by address of the jump and of its target
+    back_edges: HashMap<(usize, usize), u64>,
+    /// Address and opcode of the last instruction executed
+    previous: Option<(usize, Opcode)>,
+    instructions: u64,
+}
+
+impl Profiler {
+    /// Returns the number of instructions executed
+    #[inline]
+    pub const fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Returns the number of times the instruction at `address` was executed
+    #[inline]
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executions.get(&address).map_or(0, |&(_, count)| count)
+    }
+
+    /// Returns the number of times an instruction read `address`
+    #[inline]
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of times an instruction wrote to `address`
+    #[inline]
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Forgets everything counted so far
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns the opcodes by number of executions, and the `top` hottest addresses, reads, writes and loops
+    pub fn report(&self, top: usize) -> ProfileReport {
+        let mut opcodes = self
+            .opcodes
+            .iter()
+            .map(|(&opcode, &executions)| OpcodeCount { opcode, executions })
+            .collect::<Vec<_>>();
+        opcodes.sort_by(|a, b| {
+            b.executions
+                .cmp(&a.executions)
+                .then(a.opcode.code().cmp(&b.opcode.code()))
+        });
+
+        let hot_spots = hottest(&self.executions, |&(_, executions)| executions, top)
+            .map(|(&address, &(opcode, executions))| HotSpot {
+                address,
+                opcode,
+                executions,
+            })
+            .collect();
+
+        let loops = hottest(&self.back_edges, |&trips| trips, top)
+            .map(|(&(end, start), &trips)| Loop {
+                start,
+                end,
+                trips,
+                executions: self
+                    .executions
+                    .iter()
+                    .filter(|(address, _)| (start..=end).contains(*address))
+                    .map(|(_, &(_, executions))| executions)
+                    .sum(),
+            })
+            .collect();
+
+        ProfileReport {
+            instructions: self.instructions,
+            opcodes,
+            hot_spots,
+            loops,
+            reads: address_counts(&self.reads, top),
+            writes: address_counts(&self.writes, top),
+        }
+    }
+}
+
+/// Returns the `top` entries of `counts` with the highest count, ties broken by lowest key
+fn hottest<K, V, F>(counts: &HashMap<K, V>, count: F, top: usize) -> impl Iterator<Item = (&K, &V)>
+where
+    K: Ord,
+    F: Fn(&V) -> u64,
+{
+    let mut entries = counts.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| count(b.1).cmp(&count(a.1)).then(a.0.cmp(b.0)));
+    entries.into_iter().take(top)
+}
+
+fn address_counts(counts: &HashMap<usize, u64>, top: usize) -> Vec<AddressCount> {
+    hottest(counts, |&count| count, top)
+        .map(|(&address, &count)| AddressCount { address, count })
+        .collect()
+}
+
+#[inline]
+fn increment<K: Eq + Hash>(counts: &mut HashMap<K, u64>, key: K) {
+    *counts.entry(key).or_insert(0) += 1;
+}
+
+impl<T> Observer<T> for Profiler {
+    fn before_instruction(&mut self, address: usize, opcode: Opcode) {
+        if let Some((jump, Opcode::JmpIfTrue | Opcode::JmpIfFalse)) = self.previous {
+            if address <= jump {
+                increment(&mut self.back_edges, (jump, address));
+            }
+        }
+
+        let (last_opcode, executions) = self.executions.entry(address).or_insert((opcode, 0));
+        *last_opcode = opcode;
+        *executions += 1;
+        increment(&mut self.opcodes, opcode);
+        self.instructions += 1;
+        self.previous = Some((address, opcode));
+    }
+
+    #[inline]
+    fn on_memory_read(&mut self, address: usize, _value: &T) {
+        increment(&mut self.reads, address);
+    }
+
+    #[inline]
+    fn on_memory_write(&mut self, address: usize, _old: &T, _new: &T) {
+        increment(&mut self.writes, address);
+    }
+}
+
+/// Summary of a [Profiler], see [`profiler.report()`](Profiler::report)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ProfileReport {
+    /// Number of instructions executed
+    pub instructions: u64,
+    /// Every opcode executed, the most executed first
+    pub opcodes: Vec<OpcodeCount>,
+    /// The most executed instructions, the most executed first
+    pub hot_spots: Vec<HotSpot>,
+    /// The loops with the most trips, the most trips first
+    pub loops: Vec<Loop>,
+    /// The most read addresses, the most read first
+    pub reads: Vec<AddressCount>,
+    /// The most written addresses, the most written first
+    pub writes: Vec<AddressCount>,
+}
+
+/// Number of executions of an opcode, in a [ProfileReport]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct OpcodeCount {
+    pub opcode: Opcode,
+    pub executions: u64,
+}
+
+/// Number of executions of the instruction at an address, in a [ProfileReport]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HotSpot {
+    pub address: usize,
+    /// Opcode of the instruction the last time it was executed
+    pub opcode: Opcode,
+    pub executions: u64,
+}
+
+/// A backward jump, in a [ProfileReport]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Loop {
+    /// Address the jump goes back to
+    pub start: usize,
+    /// Address of the jump
+    pub end: usize,
+    /// Number of times the jump was taken
+    pub trips: u64,
+    /// Number of instructions executed between `start` and `end` (included), in any iteration
+    pub executions: u64,
+}
+
+/// Number of reads or writes of an address, in a [ProfileReport]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AddressCount {
+    pub address: usize,
+    pub count: u64,
+}
+
+impl ProfileReport {
+    /// Returns the report as JSON
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{profile::Profiler, IntcodeVM};
+    /// let mut vm = IntcodeVM::new([1101, 1, 2, 5, 99]).with_observer(Profiler::default());
+    /// vm.run().unwrap();
+    ///
+    /// let json = vm.observer().report(1).to_json();
+    /// assert!(json.starts_with(r#"{"instructions":2,"opcodes":[{"opcode":"Add","executions":1}"#));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report is always valid JSON")
+    }
+
+    /// Returns the percentage of the instructions executed `executions` represents
+    fn share(&self, executions: u64) -> f64 {
+        executions as f64 * 100.0 / self.instructions.max(1) as f64
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions executed", self.instructions)?;
+
+        writeln!(f, "\nOpcodes:")?;
+        for count in &self.opcodes {
+            writeln!(
+                f,
+                "  {:<4} {:>12} {:>5.1}%",
+                count.opcode.mnemonic(),
+                count.executions,
+                self.share(count.executions)
+            )?;
+        }
+
+        writeln!(f, "\nHot spots:")?;
+        for spot in &self.hot_spots {
+            writeln!(
+                f,
+                "  {:04}: {:<4} {:>12} {:>5.1}%",
+                spot.address,
+                spot.opcode.mnemonic(),
+                spot.executions,
+                self.share(spot.executions)
+            )?;
+        }
+
+        writeln!(f, "\nLoops:")?;
+        for l in &self.loops {
+            writeln!(
+                f,
+                "  {:04} -> {:04}: {} trips, {} instructions ({:.1}%)",
+                l.end,
+                l.start,
+                l.trips,
+                l.executions,
+                self.share(l.executions)
+            )?;
+        }
+
+        for (title, counts) in [("Reads", &self.reads), ("Writes", &self.writes)] {
+            writeln!(f, "\n{title}:")?;
+            for count in counts {
+                writeln!(f, "  {:04}: {:>12}", count.address, count.count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
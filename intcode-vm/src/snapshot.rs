@@ -0,0 +1,103 @@
+//! Snapshots of the whole state of a [VM](crate::vm::IntcodeVM)
+//!
+//! A [Snapshot] is taken with [`vm.snapshot()`](crate::vm::IntcodeVM::snapshot)
+//! and turned back into a VM with [`IntcodeVM::restore()`](crate::vm::IntcodeVM::restore).
+//! With the `serde` feature it implements `Serialize` and `Deserialize`, so it can be saved to disk.
+//!
+//! # Example
+//!
+//! ```
+//! # use intcode_vm::{IntcodeVM, VMResult};
+//! // outputs its input + 1, twice
+//! let mut vm = IntcodeVM::new([3, 13, 1001, 13, 1, 13, 4, 13, 1105, 1, 0, 99, 0, 0]);
+//! vm.push_input(1);
+//! assert_eq!(vm.run().unwrap(), VMResult::Output(2));
+//!
+//! let snapshot = vm.snapshot();
+//! vm.push_input(10);
+//! assert_eq!(vm.run().unwrap(), VMResult::Output(11));
+//!
+//! let mut restored = IntcodeVM::restore(snapshot).unwrap();
+//! restored.push_input(20);
+//! assert_eq!(restored.run().unwrap(), VMResult::Output(21));
+//! ```
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::arithmetic::OverflowPolicy;
+
+/// Version of the [Snapshot] format produced by this version of the crate
+///
+/// Version 1 is the memory, instruction pointer, relative base, pending inputs and overflow policy
+/// of a [Snapshot]. It is increased every time the format changes in a way older snapshots cannot be restored.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The state of a [VM](crate::vm::IntcodeVM): its memory (including whether it is paged and its limit),
+/// instruction pointer, relative base, pending inputs and [overflow policy](OverflowPolicy)
+///
+/// The [breakpoints](crate::breakpoint) and the [observer](crate::observer) are not part of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot<T> {
+    pub(crate) version: u32,
+    /// Allocated parts of the memory, as their first address and their values
+    pub(crate) memory: Vec<(usize, Vec<T>)>,
+    pub(crate) paged: bool,
+    pub(crate) memory_limit: Option<usize>,
+    pub(crate) instruction_ptr: usize,
+    pub(crate) relative_base: T,
+    pub(crate) inputs: Vec<T>,
+    pub(crate) overflow_policy: OverflowPolicy,
+}
+
+impl<T> Snapshot<T> {
+    /// Returns the version of the format of this snapshot (see [SNAPSHOT_VERSION])
+    #[inline]
+    pub const fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the address of the next instruction to be executed
+    #[inline]
+    pub const fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    /// Returns the value of the relative base
+    #[inline]
+    pub const fn relative_base(&self) -> &T {
+        &self.relative_base
+    }
+
+    /// Returns the inputs waiting to be consumed by read instructions, in order
+    #[inline]
+    pub fn pending_inputs(&self) -> &[T] {
+        &self.inputs
+    }
+
+    /// Returns what the VM does when an arithmetic operation overflows
+    #[inline]
+    pub const fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Converts every value of the snapshot with `f`
+    #[cfg(feature = "bigint")]
+    pub(crate) fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> Snapshot<U> {
+        Snapshot {
+            version: self.version,
+            memory: self
+                .memory
+                .into_iter()
+                .map(|(start, values)| (start, values.into_iter().map(&mut f).collect()))
+                .collect(),
+            paged: self.paged,
+            memory_limit: self.memory_limit,
+            instruction_ptr: self.instruction_ptr,
+            relative_base: f(self.relative_base),
+            inputs: self.inputs.into_iter().map(f).collect(),
+            overflow_policy: self.overflow_policy,
+        }
+    }
+}
@@ -1,10 +1,18 @@
-use std::str::FromStr;
+use std::{iter::FusedIterator, str::FromStr};
 
 use num::{Integer, ToPrimitive};
 
 use crate::{
-    error::{self, VMError},
-    memory::Memory,
+    arithmetic::{OverflowArithmetic, OverflowPolicy},
+    async_io::{AsyncInputSource, AsyncOutputSink},
+    breakpoint::{BreakReason, Breakpoints},
+    error::{self, VMError, VMErrorKind},
+    instruction::{Destination, ExecutedInstruction, Opcode, Operand, Step},
+    io::{InputQueue, InputSource, NoInput, OutputSink, Wiring},
+    journal::Journal,
+    memory::{Memory, MemoryBackend},
+    observer::{NoopObserver, Observer},
+    snapshot::{Snapshot, SNAPSHOT_VERSION},
 };
 
 /// A [VM](IntcodeVM) will return a variant of this enum when it encounters some instructions
@@ -14,9 +22,9 @@ pub enum VMResult<T> {
     ///
     /// Calling [`vm.run()`](IntcodeVM::run) again would simply halt immediatly again
     Halted,
-    /// Encountered opcode: 03
+    /// Encountered opcode: 03 and the input queue was empty
     ///
-    /// Need to provide an input value to the vm ([`vm.set_next_input()`](IntcodeVM::set_next_input))
+    /// Need to provide an input value to the vm ([`vm.push_input()`](IntcodeVM::push_input))
     /// before calling [`vm.run()`](IntcodeVM::run) again
     WaitingForInput,
     /// Encoutered opcode: 04
@@ -24,17 +32,54 @@ pub enum VMResult<T> {
     /// You can call [`vm.run()`](IntcodeVM::run) again without changing anything.
     /// The associated value of this variant is the output generated by the instruction.
     Output(T),
+    /// Hit one of the [breakpoints](crate::breakpoint) of the VM
+    ///
+    /// `address` is the address of the instruction that hit it.
+    /// You can call [`vm.run()`](IntcodeVM::run) again to resume the execution.
+    Breakpoint { address: usize, reason: BreakReason },
+    /// Executed as many instructions as allowed by the budgets of the VM
+    /// (see [`vm.set_run_budget()`](IntcodeVM::set_run_budget)
+    /// and [`vm.set_total_budget()`](IntcodeVM::set_total_budget))
+    ///
+    /// The next instruction was not executed yet.
+    /// Calling [`vm.run()`](IntcodeVM::run) again resets the budget of the run
+    /// (the total budget has to be raised to go further).
+    BudgetExhausted,
+}
+
+/// Why a [VM](IntcodeVM) stopped running when it was asked to run until it could not go further
+/// (see [`vm.run_until_input()`](IntcodeVM::run_until_input))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StopReason {
+    /// Encountered opcode: 99
+    Halted,
+    /// Encountered opcode: 03 and the input queue was empty
+    WaitingForInput,
+    /// Hit one of the [breakpoints](crate::breakpoint) of the VM (see [`VMResult::Breakpoint`])
+    Breakpoint { address: usize, reason: BreakReason },
+    /// Executed as many instructions as allowed by the budgets of the VM (see [`VMResult::BudgetExhausted`])
+    BudgetExhausted,
 }
 
 #[derive(Debug, Clone)]
-pub struct IntcodeVM<T>
+pub struct IntcodeVM<T, O = NoopObserver, M = Memory<T>>
 where
     T: Integer + Clone + ToPrimitive,
 {
-    memory: Memory<T>,
+    memory: M,
     instruction_ptr: usize,
     relative_base_ptr: T,
-    next_input_value: Option<T>,
+    inputs: InputQueue<T>,
+    breakpoints: Breakpoints<T>,
+    journal: Option<Journal<T>>,
+    executed_instructions: u64,
+    run_budget: Option<u64>,
+    total_budget: Option<u64>,
+    overflow_policy: OverflowPolicy,
+    observer: O,
+    /// Address of the instruction the VM stopped on after giving it to the observer
+    /// (a read waiting for an input or a halt instruction), so it is not given again
+    observed: Option<usize>,
 }
 
 impl<T> IntcodeVM<T>
@@ -51,14 +96,175 @@ where
     /// ```
     #[inline]
     pub fn new<I: Into<Memory<T>>>(memory: I) -> Self {
+        Self::from_backend(memory.into())
+    }
+
+    /// Creates a VM in the state saved by [`vm.snapshot()`](IntcodeVM::snapshot)
+    ///
+    /// Fails with [`VMErrorKind::IncompatibleSnapshot`] if the snapshot was made
+    /// with another [version](crate::snapshot::SNAPSHOT_VERSION) of the format.
+    /// Fails with [`VMErrorKind::MemoryLimitExceeded`] if its memory does not fit
+    /// in the address space, which only a deserialized snapshot can do.
+    /// See the [snapshot module](crate::snapshot) for an example.
+    pub fn restore(snapshot: Snapshot<T>) -> error::Result<Self, T> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(VMErrorKind::IncompatibleSnapshot {
+                version: snapshot.version,
+                supported: SNAPSHOT_VERSION,
+            }
+            .into());
+        }
+
+        let mut vm = Self::new(Memory::from_segments(
+            snapshot.memory,
+            snapshot.paged,
+            snapshot.memory_limit,
+        )?);
+        vm.instruction_ptr = snapshot.instruction_ptr;
+        vm.relative_base_ptr = snapshot.relative_base;
+        vm.inputs = snapshot.inputs.into();
+        vm.overflow_policy = snapshot.overflow_policy;
+        Ok(vm)
+    }
+}
+
+impl<T, M> IntcodeVM<T, NoopObserver, M>
+where
+    T: Integer + Clone + ToPrimitive,
+    M: MemoryBackend<T>,
+{
+    /// Creates a new VM storing its values in `memory`
+    ///
+    /// See [MemoryBackend] for an example.
+    #[inline]
+    pub fn from_backend(memory: M) -> Self {
         Self {
-            memory: memory.into(),
+            memory,
             instruction_ptr: 0,
             relative_base_ptr: T::zero(),
-            next_input_value: None,
+            inputs: InputQueue::default(),
+            breakpoints: Breakpoints::default(),
+            journal: None,
+            executed_instructions: 0,
+            run_budget: None,
+            total_budget: None,
+            overflow_policy: OverflowPolicy::default(),
+            observer: NoopObserver,
+            observed: None,
+        }
+    }
+}
+
+impl<T, O> IntcodeVM<T, O>
+where
+    T: Integer + Clone + ToPrimitive,
+    O: Observer<T>,
+{
+    /// Saves the memory, instruction pointer, relative base, pending inputs and overflow policy of the VM
+    ///
+    /// See the [snapshot module](crate::snapshot) for an example.
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot<T> {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            memory: self
+                .memory
+                .segments()
+                .map(|(start, values)| (start, values.to_vec()))
+                .collect(),
+            paged: self.memory.is_paged(),
+            memory_limit: self.memory.limit(),
+            instruction_ptr: self.instruction_ptr,
+            relative_base: self.relative_base_ptr.clone(),
+            inputs: self.inputs.iter().cloned().collect(),
+            overflow_policy: self.overflow_policy,
+        }
+    }
+}
+
+impl<T, O, M> IntcodeVM<T, O, M>
+where
+    T: Integer + Clone + ToPrimitive + OverflowArithmetic,
+    O: Observer<T>,
+    M: MemoryBackend<T>,
+{
+    /// Replaces the [Observer] of the VM, keeping its state
+    ///
+    /// See the [observer module](crate::observer) for an example
+    #[inline]
+    pub fn with_observer<O2: Observer<T>>(self, observer: O2) -> IntcodeVM<T, O2, M> {
+        IntcodeVM {
+            memory: self.memory,
+            instruction_ptr: self.instruction_ptr,
+            relative_base_ptr: self.relative_base_ptr,
+            inputs: self.inputs,
+            breakpoints: self.breakpoints,
+            journal: self.journal,
+            executed_instructions: self.executed_instructions,
+            run_budget: self.run_budget,
+            total_budget: self.total_budget,
+            overflow_policy: self.overflow_policy,
+            observer,
+            observed: self.observed,
         }
     }
 
+    /// Creates a copy of the VM, sharing the unchanged parts of its memory with it
+    ///
+    /// The memory is copied with [`MemoryBackend::fork()`]: a [paged](Memory::into_paged) [Memory]
+    /// is only copied one page at a time, when one of the VMs writes to it,
+    /// a contiguous one is copied right away.
+    /// Use [`vm.clone()`](Clone::clone) to copy the whole memory right away.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{memory::Memory, IntcodeVM, VMResult};
+    /// // outputs the sum of 2 inputs
+    /// let memory = Memory::from([3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99]);
+    /// let mut vm = IntcodeVM::new(memory.into_paged());
+    /// vm.push_input(40);
+    ///
+    /// let mut fork = vm.fork();
+    /// fork.push_input(2);
+    /// assert_eq!(fork.run().unwrap(), VMResult::Output(42));
+    ///
+    /// vm.push_input(60);
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(100));
+    /// ```
+    pub fn fork(&self) -> Self
+    where
+        O: Clone,
+        M: Clone,
+    {
+        Self {
+            memory: self.memory.fork(),
+            instruction_ptr: self.instruction_ptr,
+            relative_base_ptr: self.relative_base_ptr.clone(),
+            inputs: self.inputs.clone(),
+            breakpoints: self.breakpoints.clone(),
+            journal: self.journal.clone(),
+            executed_instructions: self.executed_instructions,
+            run_budget: self.run_budget,
+            total_budget: self.total_budget,
+            overflow_policy: self.overflow_policy,
+            observer: self.observer.clone(),
+            observed: self.observed,
+        }
+    }
+
+    /// Returns a reference to the [Observer] of the VM
+    #[inline]
+    pub const fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns a mutable reference to the [Observer] of the VM
+    #[inline]
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
     /// Executes the intcode program in the memory of the VM
     ///
     /// When a halt instruction is encountered, returns [`Ok(VMResult::Halted)`](VMResult::Halted)
@@ -79,110 +285,579 @@ where
     /// ```
     #[inline]
     pub fn run(&mut self) -> error::Result<VMResult<T>, T> {
+        let run_start = self.executed_instructions;
+        self.run_inner(&mut NoInput, run_start)
+    }
+
+    /// Same as [`vm.run()`](IntcodeVM::run), but once the input queue is empty,
+    /// read instructions take their value from `input`
+    ///
+    /// `run_start` is the number of instructions executed when the run started, to enforce the run budget
+    #[inline]
+    fn run_inner<I>(&mut self, input: &mut I, run_start: u64) -> error::Result<VMResult<T>, T>
+    where
+        I: InputSource<T> + ?Sized,
+    {
         loop {
-            let instruction = instr::Instruction::from_current_instr_ptr(self)?;
-            let instruction_width = instruction.instruction_width();
-            match instruction {
-                instr::Instruction::Add(arg1, arg2, dest) => {
-                    let arg1_val = arg1.resolve_value(self)?;
-                    let arg2_val = arg2.resolve_value(self)?;
-                    let destination_addr = dest.resolve_address(self)?;
-
-                    let result = arg1_val.clone() + arg2_val.clone();
-                    self.memory.set(destination_addr, result);
-                    self.increment_instr_ptr_by(instruction_width);
+            let address = self.instruction_ptr;
+            if self.breakpoints.is_active() {
+                let memory = &self.memory;
+                if let Some((address, reason)) = self.breakpoints.check_before(
+                    address,
+                    |address| memory.read(address),
+                    &self.relative_base_ptr,
+                ) {
+                    return Ok(VMResult::Breakpoint { address, reason });
                 }
+            }
 
-                instr::Instruction::Mul(arg1, arg2, dest) => {
-                    let arg1_val = arg1.resolve_value(self)?;
-                    let arg2_val = arg2.resolve_value(self)?;
-                    let destination_addr = dest.resolve_address(self)?;
-
-                    let result = arg1_val.clone() * arg2_val.clone();
-                    self.memory.set(destination_addr, result);
-                    self.increment_instr_ptr_by(instruction_width);
+            let instruction = match instr::Instruction::from_current_instr_ptr(self) {
+                Ok(instruction) => instruction,
+                Err(error) => {
+                    self.breakpoints.discard_hit();
+                    return Err(self.with_context(error));
                 }
+            };
+            if instruction.opcode() != Opcode::Halt && self.budget_exhausted(run_start) {
+                return Ok(VMResult::BudgetExhausted);
+            }
 
-                instr::Instruction::ReadInput(dest) => {
-                    let destination_addr = dest.resolve_address(self)?;
-                    if let Some(input) = self.next_input_value.take() {
-                        self.memory.set(destination_addr, input);
-                        self.increment_instr_ptr_by(instruction_width);
-                    } else {
-                        return Ok(VMResult::WaitingForInput);
-                    }
+            let result = match self.execute_journaled(instruction, input) {
+                Ok(result) => result,
+                Err(error) => {
+                    self.breakpoints.discard_hit();
+                    return Err(self.with_context(error));
                 }
+            };
 
-                instr::Instruction::WriteOutput(arg) => {
-                    let res = arg.resolve_value(self)?.clone();
-                    self.increment_instr_ptr_by(instruction_width);
-                    return Ok(VMResult::Output(res));
+            if !matches!(result, Some(VMResult::WaitingForInput)) {
+                let breakpoint = self.breakpoints.after_execute(address, result.is_some());
+                if matches!(result, Some(VMResult::Halted)) {
+                    self.breakpoints.on_halt(address);
                 }
+                if let Some((address, reason)) = breakpoint {
+                    return Ok(VMResult::Breakpoint { address, reason });
+                }
+            }
 
-                instr::Instruction::JmpIfTrue(arg, target) => {
-                    if !arg.resolve_value(self)?.is_zero() {
-                        let target_value = target.resolve_value(self)?;
-                        let new_instr_ptr = target_value
-                            .to_usize()
-                            .ok_or_else(|| VMError::CannotCastToUsize(target_value.clone()))?;
+            if let Some(result) = result {
+                return Ok(result);
+            }
+        }
+    }
 
-                        self.instruction_ptr = new_instr_ptr;
-                    } else {
-                        self.increment_instr_ptr_by(instruction_width);
-                    }
-                }
+    /// Executes exactly one instruction (the one at the instruction pointer)
+    /// and returns a description of what it did
+    ///
+    /// Read instructions only take their value from the input queue.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{instruction::{ArgMode, Destination, Opcode, Operand, Step}, IntcodeVM};
+    /// let mut vm = IntcodeVM::new([1001, 4, 5, 0, 99]);
+    ///
+    /// let Step::Executed(executed) = vm.step().unwrap() else { unreachable!() };
+    /// assert_eq!(executed.address, 0);
+    /// assert_eq!(executed.opcode, Opcode::Add);
+    /// assert_eq!(
+    ///     executed.operands,
+    ///     vec![
+    ///         Operand { mode: ArgMode::Positional, raw: 4, value: Some(99) },
+    ///         Operand { mode: ArgMode::Immediate, raw: 5, value: Some(5) },
+    ///     ]
+    /// );
+    /// assert_eq!(
+    ///     executed.destination,
+    ///     Some(Destination { mode: ArgMode::Positional, raw: 0, address: 0, value: 104 })
+    /// );
+    /// assert_eq!(executed.next_instruction_ptr, 4);
+    /// ```
+    ///
+    /// Nothing is executed if the VM needs an input and the input queue is empty
+    /// ```
+    /// # use intcode_vm::{instruction::Step, IntcodeVM};
+    /// let mut vm = IntcodeVM::new([3, 0, 99]);
+    ///
+    /// assert_eq!(vm.step().unwrap(), Step::WaitingForInput);
+    /// assert_eq!(vm.instruction_ptr(), 0);
+    /// ```
+    pub fn step(&mut self) -> error::Result<Step<T>, T> {
+        let result = self.step_inner();
+        result.map_err(|error| {
+            self.breakpoints.discard_hit();
+            self.with_context(error)
+        })
+    }
+
+    /// Same as [`vm.step()`](IntcodeVM::step), without adding context to the errors
+    fn step_inner(&mut self) -> error::Result<Step<T>, T> {
+        let address = self.instruction_ptr;
+        let instruction = instr::Instruction::from_current_instr_ptr(self)?;
+        let opcode = instruction.opcode();
+
+        let mut operands = Vec::with_capacity(opcode.arg_count());
+        let mut destination = None;
+        for arg in instruction.args() {
+            if opcode.destination_arg() == Some(arg.arg_num()) {
+                destination = Some((
+                    arg.mode(),
+                    arg.raw().clone(),
+                    arg.resolve_address(self.instruction_ptr, &self.relative_base_ptr)?,
+                ));
+            } else {
+                operands.push(Operand {
+                    mode: arg.mode(),
+                    raw: arg.raw().clone(),
+                    value: arg
+                        .resolve_value(&self.memory, self.instruction_ptr, &self.relative_base_ptr)
+                        .ok(),
+                });
+            }
+        }
+
+        if let Some(VMResult::WaitingForInput) =
+            self.execute_journaled(instruction, &mut NoInput)?
+        {
+            return Ok(Step::WaitingForInput);
+        }
+
+        self.breakpoints.discard_hit();
+        if opcode == Opcode::Halt {
+            self.breakpoints.on_halt(address);
+        }
+
+        let destination = destination.map(|(mode, raw, address)| Destination {
+            mode,
+            raw,
+            address,
+            value: self.memory.read(address),
+        });
+
+        Ok(Step::Executed(ExecutedInstruction {
+            address,
+            opcode,
+            operands,
+            destination,
+            next_instruction_ptr: self.instruction_ptr,
+        }))
+    }
+
+    /// Same as [`execute()`](IntcodeVM::execute), but gives the instruction to the observer
+    /// (unless it already was), records it in the journal (if enabled) and counts it if it was executed
+    #[inline]
+    fn execute_journaled<I>(
+        &mut self,
+        instruction: instr::Instruction<T>,
+        input: &mut I,
+    ) -> error::Result<Option<VMResult<T>>, T>
+    where
+        I: InputSource<T> + ?Sized,
+    {
+        let address = self.instruction_ptr;
+        if self.observed != Some(address) {
+            self.observer
+                .before_instruction(address, instruction.opcode());
+        }
+
+        if let Some(journal) = &mut self.journal {
+            journal.begin(self.instruction_ptr, self.relative_base_ptr.clone());
+        }
 
-                instr::Instruction::JmpIfFalse(arg, target) => {
-                    if arg.resolve_value(self)?.is_zero() {
-                        let target_value = target.resolve_value(self)?;
-                        let new_instr_ptr = target_value
-                            .to_usize()
-                            .ok_or_else(|| VMError::CannotCastToUsize(target_value.clone()))?;
+        let result = self.execute(instruction, input);
+        let executed = !matches!(
+            result,
+            Ok(Some(VMResult::WaitingForInput | VMResult::Halted)) | Err(_)
+        );
 
-                        self.instruction_ptr = new_instr_ptr;
-                    } else {
-                        self.increment_instr_ptr_by(instruction_width);
+        if let Some(journal) = &mut self.journal {
+            journal.end(executed);
+        }
+
+        if executed {
+            self.executed_instructions += 1;
+        }
+        self.observed = match result {
+            Ok(Some(VMResult::WaitingForInput | VMResult::Halted)) => Some(address),
+            _ => None,
+        };
+
+        result
+    }
+
+    /// Executes `instruction` (which must have been decoded at the instruction pointer)
+    ///
+    /// Returns [`Some`] if [`vm.run()`](IntcodeVM::run) should return, [`None`] if it can keep going
+    #[inline]
+    fn execute<I>(
+        &mut self,
+        instruction: instr::Instruction<T>,
+        input: &mut I,
+    ) -> error::Result<Option<VMResult<T>>, T>
+    where
+        I: InputSource<T> + ?Sized,
+    {
+        let instruction_width = instruction.instruction_width();
+        match instruction {
+            instr::Instruction::Add(arg1, arg2, dest) => {
+                let arg1_val = self.arg_value(&arg1)?;
+                let arg2_val = self.arg_value(&arg2)?;
+                let destination_addr =
+                    dest.resolve_address(self.instruction_ptr, &self.relative_base_ptr)?;
+
+                let result = self.overflow_checked(
+                    Opcode::Add,
+                    arg1_val.add_with(&arg2_val, self.overflow_policy),
+                )?;
+                self.write_memory(destination_addr, result)?;
+                self.increment_instr_ptr_by(instruction_width);
+            }
+
+            instr::Instruction::Mul(arg1, arg2, dest) => {
+                let arg1_val = self.arg_value(&arg1)?;
+                let arg2_val = self.arg_value(&arg2)?;
+                let destination_addr =
+                    dest.resolve_address(self.instruction_ptr, &self.relative_base_ptr)?;
+
+                let result = self.overflow_checked(
+                    Opcode::Mul,
+                    arg1_val.mul_with(&arg2_val, self.overflow_policy),
+                )?;
+                self.write_memory(destination_addr, result)?;
+                self.increment_instr_ptr_by(instruction_width);
+            }
+
+            instr::Instruction::ReadInput(dest) => {
+                let destination_addr =
+                    dest.resolve_address(self.instruction_ptr, &self.relative_base_ptr)?;
+                // checked before taking the input, so it is not lost
+                self.memory.check_write(destination_addr)?;
+                if let Some(input) = self.inputs.pop_front().or_else(|| input.next_input()) {
+                    self.observer.on_input(&input);
+                    if let Some(journal) = &mut self.journal {
+                        journal.record_input(input.clone());
                     }
+                    self.write_memory(destination_addr, input)?;
+                    self.increment_instr_ptr_by(instruction_width);
+                } else {
+                    return Ok(Some(VMResult::WaitingForInput));
                 }
+            }
 
-                instr::Instruction::LessThan(arg1, arg2, result) => {
-                    let arg1_val = arg1.resolve_value(self)?;
-                    let arg2_val = arg2.resolve_value(self)?;
-                    let dest = result.resolve_address(self)?;
-                    if arg1_val < arg2_val {
-                        self.memory.set(dest, T::one());
-                    } else {
-                        self.memory.set(dest, T::zero());
-                    }
+            instr::Instruction::WriteOutput(arg) => {
+                let res = self.arg_value(&arg)?;
+                self.observer.on_output(&res);
+                self.increment_instr_ptr_by(instruction_width);
+                return Ok(Some(VMResult::Output(res)));
+            }
+
+            instr::Instruction::JmpIfTrue(arg, target) => {
+                if !self.arg_value(&arg)?.is_zero() {
+                    let target_value = self.arg_value(&target)?;
+                    let new_instr_ptr = target_value.to_usize().ok_or_else(|| {
+                        VMError::from(VMErrorKind::CannotCastToUsize(target_value))
+                            .with_arg(target.arg_num())
+                    })?;
 
+                    self.instruction_ptr = new_instr_ptr;
+                } else {
                     self.increment_instr_ptr_by(instruction_width);
                 }
+            }
 
-                instr::Instruction::Equals(arg1, arg2, result) => {
-                    let arg1_val = arg1.resolve_value(self)?;
-                    let arg2_val = arg2.resolve_value(self)?;
-                    let dest = result.resolve_address(self)?;
-                    if arg1_val == arg2_val {
-                        self.memory.set(dest, T::one());
-                    } else {
-                        self.memory.set(dest, T::zero());
-                    }
+            instr::Instruction::JmpIfFalse(arg, target) => {
+                if self.arg_value(&arg)?.is_zero() {
+                    let target_value = self.arg_value(&target)?;
+                    let new_instr_ptr = target_value.to_usize().ok_or_else(|| {
+                        VMError::from(VMErrorKind::CannotCastToUsize(target_value))
+                            .with_arg(target.arg_num())
+                    })?;
 
+                    self.instruction_ptr = new_instr_ptr;
+                } else {
                     self.increment_instr_ptr_by(instruction_width);
                 }
+            }
 
-                instr::Instruction::AddRelativeBase(arg) => {
-                    let arg_val = arg.resolve_value(self)?;
-                    self.relative_base_ptr = self.relative_base_ptr.clone() + arg_val.clone();
+            instr::Instruction::LessThan(arg1, arg2, result) => {
+                let arg1_val = self.arg_value(&arg1)?;
+                let arg2_val = self.arg_value(&arg2)?;
+                let dest = result.resolve_address(self.instruction_ptr, &self.relative_base_ptr)?;
+                if arg1_val < arg2_val {
+                    self.write_memory(dest, T::one())?;
+                } else {
+                    self.write_memory(dest, T::zero())?;
+                }
 
-                    self.increment_instr_ptr_by(instruction_width);
+                self.increment_instr_ptr_by(instruction_width);
+            }
+
+            instr::Instruction::Equals(arg1, arg2, result) => {
+                let arg1_val = self.arg_value(&arg1)?;
+                let arg2_val = self.arg_value(&arg2)?;
+                let dest = result.resolve_address(self.instruction_ptr, &self.relative_base_ptr)?;
+                if arg1_val == arg2_val {
+                    self.write_memory(dest, T::one())?;
+                } else {
+                    self.write_memory(dest, T::zero())?;
                 }
 
-                instr::Instruction::Halt => return Ok(VMResult::Halted),
+                self.increment_instr_ptr_by(instruction_width);
+            }
+
+            instr::Instruction::AddRelativeBase(arg) => {
+                let arg_val = self.arg_value(&arg)?;
+                let new_relative_base = self.overflow_checked(
+                    Opcode::AddRelativeBase,
+                    self.relative_base_ptr
+                        .add_with(&arg_val, self.overflow_policy),
+                )?;
+                self.observer
+                    .on_relative_base_change(&self.relative_base_ptr, &new_relative_base);
+                self.relative_base_ptr = new_relative_base;
+
+                self.increment_instr_ptr_by(instruction_width);
+            }
+
+            instr::Instruction::Halt => return Ok(Some(VMResult::Halted)),
+        }
+
+        Ok(None)
+    }
+
+    /// Runs the VM until it halts, collecting every value it outputs along the way
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use intcode_vm::IntcodeVM;
+    /// // outputs its input twice
+    /// let mut vm = IntcodeVM::new([3, 0, 4, 0, 4, 0, 99]);
+    /// vm.push_input(7);
+    ///
+    /// assert_eq!(vm.run_until_halt().unwrap(), vec![7, 7]);
+    /// ```
+    ///
+    /// Will return [`VMErrorKind::MissingInput`] (containing the values output so far)
+    /// if the VM asks for an input while the input queue is empty
+    /// ```
+    /// # use intcode_vm::{error::VMErrorKind, IntcodeVM};
+    /// let mut vm = IntcodeVM::new([104, 1, 3, 0, 99]);
+    ///
+    /// match vm.run_until_halt().map_err(|error| error.kind) {
+    ///     Err(VMErrorKind::MissingInput { outputs }) => assert_eq!(outputs, vec![1]),
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    /// ```
+    pub fn run_until_halt(&mut self) -> error::Result<Vec<T>, T> {
+        match self.run_until_input()? {
+            (outputs, StopReason::Halted) => Ok(outputs),
+            (outputs, StopReason::WaitingForInput) => {
+                Err(self.with_context(VMErrorKind::MissingInput { outputs }.into()))
+            }
+            (outputs, reason) => {
+                Err(self.with_context(VMErrorKind::Stopped { reason, outputs }.into()))
             }
         }
     }
 
+    /// Runs the VM until it halts or asks for an input while the input queue is empty,
+    /// collecting every value it outputs along the way
+    ///
+    /// Returns the outputs alongside the reason the VM stopped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, StopReason};
+    /// // outputs its input twice, forever
+    /// let mut vm = IntcodeVM::new([3, 9, 4, 9, 4, 9, 1105, 1, 0]);
+    ///
+    /// vm.push_input(7);
+    /// assert_eq!(vm.run_until_input().unwrap(), (vec![7, 7], StopReason::WaitingForInput));
+    ///
+    /// vm.extend_inputs([1, 2]);
+    /// assert_eq!(vm.run_until_input().unwrap(), (vec![1, 1, 2, 2], StopReason::WaitingForInput));
+    /// ```
+    pub fn run_until_input(&mut self) -> error::Result<(Vec<T>, StopReason), T> {
+        let mut outputs = Vec::new();
+        let stop_reason = self.run_with(&mut NoInput, &mut outputs)?;
+        Ok((outputs, stop_reason))
+    }
+
+    /// Runs the VM, reading from `input` once the input queue is empty and writing every output to `output`
+    ///
+    /// Stops when the VM halts or when neither the input queue nor `input` have a value available.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::VecDeque;
+    /// # use intcode_vm::{IntcodeVM, StopReason};
+    /// // outputs twice its input, forever
+    /// let mut vm = IntcodeVM::new([3, 9, 102, 2, 9, 9, 4, 9, 1105, 1, 0]);
+    /// let mut input = VecDeque::from([1, 2, 3]);
+    /// let mut sum = 0;
+    ///
+    /// let stop = vm.run_with(&mut input, &mut |out| sum += out).unwrap();
+    /// assert_eq!(stop, StopReason::WaitingForInput);
+    /// assert_eq!(sum, 12);
+    /// ```
+    pub fn run_with<I, S>(&mut self, input: &mut I, output: &mut S) -> error::Result<StopReason, T>
+    where
+        I: InputSource<T> + ?Sized,
+        S: OutputSink<T> + ?Sized,
+    {
+        self.run_device(&mut Wiring { input, output })
+    }
+
+    /// Same as [`vm.run_with()`](IntcodeVM::run_with) when a single value is both the input and the output
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{io::{InputSource, OutputSink}, IntcodeVM, StopReason};
+    /// struct Accumulator {
+    ///     total: i64,
+    ///     remaining: usize,
+    /// }
+    ///
+    /// impl InputSource<i64> for Accumulator {
+    ///     fn next_input(&mut self) -> Option<i64> {
+    ///         self.remaining = self.remaining.checked_sub(1)?;
+    ///         Some(self.total)
+    ///     }
+    /// }
+    ///
+    /// impl OutputSink<i64> for Accumulator {
+    ///     fn write_output(&mut self, value: i64) {
+    ///         self.total += value;
+    ///     }
+    /// }
+    ///
+    /// // outputs its input plus one, forever
+    /// let mut vm = IntcodeVM::new([3, 9, 101, 1, 9, 9, 4, 9, 1105, 1, 0]);
+    /// let mut device = Accumulator { total: 0, remaining: 4 };
+    ///
+    /// assert_eq!(vm.run_device(&mut device).unwrap(), StopReason::WaitingForInput);
+    /// assert_eq!(device.total, 15);
+    /// ```
+    pub fn run_device<D>(&mut self, device: &mut D) -> error::Result<StopReason, T>
+    where
+        D: InputSource<T> + OutputSink<T> + ?Sized,
+    {
+        let run_start = self.executed_instructions;
+        loop {
+            match self.run_inner(device, run_start)? {
+                VMResult::Output(value) => device.write_output(value),
+                VMResult::WaitingForInput => return Ok(StopReason::WaitingForInput),
+                VMResult::Halted => return Ok(StopReason::Halted),
+                VMResult::Breakpoint { address, reason } => {
+                    return Ok(StopReason::Breakpoint { address, reason })
+                }
+                VMResult::BudgetExhausted => return Ok(StopReason::BudgetExhausted),
+            }
+        }
+    }
+
+    /// Runs the VM asynchronously, awaiting `input` once the input queue is empty
+    /// and sending every output to `output`
+    ///
+    /// Stops when the VM halts or when `input` has no more values while the VM needs one
+    /// (e.g. the channel was closed). Does not depend on any async runtime,
+    /// see the [async_io module](crate::async_io) for an example connecting several VMs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use futures::{channel::mpsc, executor::block_on};
+    /// # use intcode_vm::{IntcodeVM, StopReason};
+    /// // outputs twice its input, forever
+    /// let mut vm = IntcodeVM::new([3, 9, 102, 2, 9, 9, 4, 9, 1105, 1, 0]);
+    /// let (sender, receiver) = mpsc::unbounded();
+    /// sender.unbounded_send(21).unwrap();
+    /// drop(sender);
+    ///
+    /// let mut outputs = Vec::new();
+    /// let stop = block_on(vm.run_async(receiver, &mut outputs)).unwrap();
+    /// assert_eq!(stop, StopReason::WaitingForInput);
+    /// assert_eq!(outputs, vec![42]);
+    /// ```
+    pub async fn run_async<I, S>(
+        &mut self,
+        mut input: I,
+        mut output: S,
+    ) -> error::Result<StopReason, T>
+    where
+        I: AsyncInputSource<T>,
+        S: AsyncOutputSink<T>,
+    {
+        let run_start = self.executed_instructions;
+        loop {
+            match self.run_inner(&mut NoInput, run_start)? {
+                VMResult::Output(value) => output.write_output(value).await,
+                VMResult::WaitingForInput => match input.next_input().await {
+                    Some(value) => self.inputs.push_back(value),
+                    None => return Ok(StopReason::WaitingForInput),
+                },
+                VMResult::Halted => return Ok(StopReason::Halted),
+                VMResult::Breakpoint { address, reason } => {
+                    return Ok(StopReason::Breakpoint { address, reason })
+                }
+                VMResult::BudgetExhausted => return Ok(StopReason::BudgetExhausted),
+            }
+        }
+    }
+
+    /// Creates an [iterator](Iterator) running the VM until each of its next outputs
+    ///
+    /// The iterator ends when the VM halts or asks for an input while the input queue is empty
+    /// (use [`outputs.stop_reason()`](Outputs::stop_reason) to know which),
+    /// and after yielding an error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, StopReason};
+    /// let mut vm = IntcodeVM::new([104, 1, 104, 2, 3, 0, 99]);
+    /// let mut outputs = vm.outputs();
+    ///
+    /// assert_eq!(outputs.next().unwrap().unwrap(), 1);
+    /// assert_eq!(outputs.next().unwrap().unwrap(), 2);
+    /// assert!(outputs.next().is_none());
+    /// assert_eq!(outputs.stop_reason(), Some(StopReason::WaitingForInput));
+    /// ```
+    #[inline]
+    pub fn outputs(&mut self) -> Outputs<'_, T, NoInput, O, M> {
+        self.outputs_with(NoInput)
+    }
+
+    /// Same as [`vm.outputs()`](IntcodeVM::outputs), but once the input queue is empty,
+    /// read instructions take their value from `input`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{io::InputIter, IntcodeVM, StopReason};
+    /// // outputs twice its input, forever
+    /// let mut vm = IntcodeVM::new([3, 9, 102, 2, 9, 9, 4, 9, 1105, 1, 0]);
+    /// let mut outputs = vm.outputs_with(InputIter::new([1, 2, 3]));
+    ///
+    /// let doubled = outputs.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(doubled, vec![2, 4, 6]);
+    /// assert_eq!(outputs.stop_reason(), Some(StopReason::WaitingForInput));
+    /// ```
+    #[inline]
+    pub fn outputs_with<I: InputSource<T>>(&mut self, input: I) -> Outputs<'_, T, I, O, M> {
+        Outputs {
+            run_start: self.executed_instructions,
+            vm: self,
+            input,
+            stop_reason: None,
+            failed: false,
+        }
+    }
+
     /// Returns the internal [Memory] of the VM
     ///
     /// # Example
@@ -195,18 +870,159 @@ where
     /// assert!(memory.memory_starts_with([1, 0, 0, 3, 99].iter()));
     /// ```
     #[inline]
-    pub fn into_memory(self) -> Memory<T> {
+    pub fn into_memory(self) -> M {
         self.memory
     }
 
+    /// Returns the input value the next read instruction will consume, if any
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::IntcodeVM;
+    /// let mut vm = IntcodeVM::new([3, 0, 99]);
+    /// assert_eq!(vm.get_next_input(), &None);
+    ///
+    /// vm.extend_inputs([1, 2]);
+    /// assert_eq!(vm.get_next_input(), &Some(1));
+    /// ```
     #[inline]
     pub const fn get_next_input(&self) -> &Option<T> {
-        &self.next_input_value
+        self.inputs.next()
     }
 
+    /// Same as [`vm.get_next_input()`](IntcodeVM::get_next_input), as an [`Option`] of a reference
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::IntcodeVM;
+    /// let mut vm = IntcodeVM::new([3, 0, 99]);
+    /// vm.push_input(1);
+    /// assert_eq!(vm.peek_input(), Some(&1));
+    /// ```
+    #[inline]
+    pub fn peek_input(&self) -> Option<&T> {
+        self.inputs.next().as_ref()
+    }
+
+    /// Replaces every pending input with `next_input`
+    ///
+    /// Returns the input that would have been consumed next, if there was one.
+    /// Use [`vm.push_input()`](IntcodeVM::push_input) to queue a value without discarding the others.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::IntcodeVM;
+    /// let mut vm = IntcodeVM::new([3, 0, 99]);
+    /// assert_eq!(vm.set_next_input(1), None);
+    /// assert_eq!(vm.set_next_input(2), Some(1));
+    /// assert_eq!(vm.peek_input(), Some(&2));
+    /// ```
     #[inline]
     pub fn set_next_input(&mut self, next_input: T) -> Option<T> {
-        self.next_input_value.replace(next_input)
+        let previous = self.inputs.pop_front();
+        self.inputs.clear();
+        self.inputs.push_back(next_input);
+        previous
+    }
+
+    /// Adds `input` at the end of the input queue
+    ///
+    /// Read instructions consume the queue in order, the VM only returns
+    /// [`VMResult::WaitingForInput`] once it is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, VMResult};
+    /// // reads two values and outputs their sum
+    /// let mut vm = IntcodeVM::new([3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99]);
+    /// vm.push_input(12);
+    /// vm.push_input(30);
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+    /// ```
+    #[inline]
+    pub fn push_input(&mut self, input: T) {
+        self.inputs.push_back(input);
+    }
+
+    /// Adds every value of `inputs` at the end of the input queue (in order)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, VMResult};
+    /// let mut vm = IntcodeVM::new([3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99]);
+    /// vm.extend_inputs([12, 30]);
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+    /// assert_eq!(vm.run().unwrap(), VMResult::Halted);
+    /// ```
+    #[inline]
+    pub fn extend_inputs<I: IntoIterator<Item = T>>(&mut self, inputs: I) {
+        self.inputs.extend(inputs);
+    }
+
+    /// Returns the number of inputs waiting to be consumed by read instructions
+    #[inline]
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns the value `arg` resolves to, reading memory if needed
+    #[inline]
+    fn arg_value(&mut self, arg: &instr::ArgInfo<T>) -> error::Result<T, T> {
+        match arg.address(self.instruction_ptr, &self.relative_base_ptr)? {
+            Some(address) => Ok(self.read_memory(address)),
+            None => Ok(arg.raw().clone()),
+        }
+    }
+
+    #[inline]
+    fn read_memory(&mut self, address: usize) -> T {
+        self.breakpoints.on_read(address);
+        let value = self.memory.read(address);
+        self.observer.on_memory_read(address, &value);
+        value
+    }
+
+    #[inline]
+    fn write_memory(&mut self, address: usize, value: T) -> error::Result<(), T> {
+        self.memory.check_write(address)?;
+
+        let old = self.memory.read(address);
+        self.breakpoints.on_write(address, &old, &value);
+        self.observer.on_memory_write(address, &old, &value);
+        if let Some(journal) = &mut self.journal {
+            journal.record_write(address, old);
+        }
+        self.memory.try_set(address, value)
+    }
+
+    /// Returns the result of an arithmetic operation of the current instruction,
+    /// fails if it overflowed
+    #[inline]
+    fn overflow_checked(&self, opcode: Opcode, result: Option<T>) -> error::Result<T, T> {
+        result.ok_or_else(|| {
+            VMErrorKind::ArithmeticOverflow {
+                ip: self.instruction_ptr,
+                opcode,
+            }
+            .into()
+        })
+    }
+
+    /// Adds the current state of the VM to `error`
+    #[cold]
+    fn with_context(&self, error: VMError<T>) -> VMError<T> {
+        error.with_context(
+            self.instruction_ptr,
+            self.relative_base_ptr.clone(),
+            |address| self.memory.read(address),
+        )
     }
 
     #[inline]
@@ -214,23 +1030,227 @@ where
         self.instruction_ptr += incr;
     }
 
+    /// Returns the address of the next instruction to be executed
     #[inline]
-    fn get_at_instr_ptr(&self, offset: usize) -> &T {
-        self.memory.get(self.instruction_ptr + offset)
+    pub const fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
     }
 
+    /// Returns the current value of the relative base
     #[inline]
-    fn get_3_after_intr_ptr(&self) -> (&T, &T, &T) {
-        (
-            self.get_at_instr_ptr(1),
-            self.get_at_instr_ptr(2),
-            self.get_at_instr_ptr(3),
-        )
+    pub const fn relative_base(&self) -> &T {
+        &self.relative_base_ptr
+    }
+
+    /// Returns a reference to the [Memory] of the VM
+    #[inline]
+    pub const fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    /// Returns a mutable reference to the [Memory] of the VM
+    ///
+    /// Changes made through it are not reported to the [observer](crate::observer)
+    /// nor to the [watchpoints](crate::breakpoint).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, VMResult};
+    /// let mut vm = IntcodeVM::new([4, 3, 99, 0]);
+    /// vm.memory_mut().set(3, 42);
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+    /// ```
+    #[inline]
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    /// Starts recording the last `capacity` executed instructions in the [journal](crate::journal),
+    /// so they can be undone
+    ///
+    /// If the journal is already enabled, only changes its capacity
+    /// (forgetting the oldest instructions if needed).
+    #[inline]
+    pub fn enable_journal(&mut self, capacity: usize) {
+        match &mut self.journal {
+            Some(journal) => journal.set_capacity(capacity),
+            None => self.journal = Some(Journal::new(capacity)),
+        }
+    }
+
+    /// Stops recording the executed instructions and forgets the recorded ones
+    #[inline]
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Returns the [journal](crate::journal) of the VM, if it is enabled
+    #[inline]
+    pub const fn journal(&self) -> Option<&Journal<T>> {
+        self.journal.as_ref()
+    }
+
+    /// Undoes the last instruction recorded in the [journal](crate::journal)
+    ///
+    /// Restores the instruction pointer, the relative base and the memory cell it wrote to.
+    /// If it consumed an input, that input is put back at the front of the input queue
+    /// (values it output cannot be taken back).
+    /// Returns `false` if there is nothing to undo (or the journal is not enabled).
+    ///
+    /// The [observer](crate::observer) and the [watchpoints](crate::breakpoint) are not notified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, VMResult};
+    /// let mut vm = IntcodeVM::new([3, 7, 4, 7, 99, 0, 0, 0]);
+    /// vm.enable_journal(10);
+    /// vm.push_input(42);
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(42));
+    ///
+    /// assert!(vm.step_back());
+    /// assert_eq!(vm.instruction_ptr(), 2);
+    /// assert!(vm.step_back());
+    /// assert_eq!(vm.instruction_ptr(), 0);
+    /// assert_eq!(vm.memory()[7], 0);
+    /// assert_eq!(vm.peek_input(), Some(&42));
+    /// assert!(!vm.step_back());
+    /// ```
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.journal.as_mut().and_then(Journal::pop) else {
+            return false;
+        };
+
+        if let Some((address, value)) = entry.write {
+            // the value was already written to that address, so it cannot fail
+            let _ = self.memory.try_set(address, value);
+        }
+
+        if let Some(input) = entry.input {
+            self.inputs.push_front(input);
+        }
+
+        self.instruction_ptr = entry.instruction_ptr;
+        self.relative_base_ptr = entry.relative_base;
+        self.observed = None;
+        true
+    }
+
+    /// Undoes instructions (see [`vm.step_back()`](IntcodeVM::step_back))
+    /// until `predicate` returns `true` for the VM
+    ///
+    /// Returns `false` if the journal ran out of instructions before that.
+    /// See the [journal module](crate::journal) for an example.
+    pub fn run_backwards_until<P>(&mut self, mut predicate: P) -> bool
+    where
+        P: FnMut(&Self) -> bool,
+    {
+        while self.step_back() {
+            if predicate(self) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Limits the number of instructions a single call to [`vm.run()`](IntcodeVM::run)
+    /// (or [`vm.run_until_halt()`](IntcodeVM::run_until_halt), [`vm.run_device()`](IntcodeVM::run_device),
+    /// a single [`vm.outputs()`](IntcodeVM::outputs) iterator...) can execute, [`None`] to remove the limit
+    ///
+    /// Once it is reached, the VM returns [`VMResult::BudgetExhausted`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, VMResult};
+    /// // loops forever
+    /// let mut vm = IntcodeVM::new([1105, 1, 0]);
+    /// vm.set_run_budget(Some(1000));
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::BudgetExhausted);
+    /// assert_eq!(vm.executed_instructions(), 1000);
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::BudgetExhausted);
+    /// assert_eq!(vm.executed_instructions(), 2000);
+    /// ```
+    #[inline]
+    pub fn set_run_budget(&mut self, budget: Option<u64>) {
+        self.run_budget = budget;
+    }
+
+    /// Limits the number of instructions the VM can execute over its whole life
+    /// (see [`vm.executed_instructions()`](IntcodeVM::executed_instructions)), [`None`] to remove the limit
+    ///
+    /// Once it is reached, the VM returns [`VMResult::BudgetExhausted`].
+    /// Instructions executed by [`vm.step()`](IntcodeVM::step) are counted but never refused.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use intcode_vm::{IntcodeVM, VMResult};
+    /// // outputs 1, 2, 3, ... forever
+    /// let mut vm = IntcodeVM::new([1001, 9, 1, 9, 4, 9, 1105, 1, 0, 0]);
+    /// vm.set_total_budget(Some(6));
+    ///
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(1));
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(2));
+    /// assert_eq!(vm.run().unwrap(), VMResult::BudgetExhausted);
+    /// assert_eq!(vm.run().unwrap(), VMResult::BudgetExhausted);
+    ///
+    /// vm.set_total_budget(None);
+    /// assert_eq!(vm.run().unwrap(), VMResult::Output(3));
+    /// ```
+    #[inline]
+    pub fn set_total_budget(&mut self, budget: Option<u64>) {
+        self.total_budget = budget;
+    }
+
+    /// Sets what the VM does when an `ADD`, a `MUL` or an `ARB` instruction overflows
+    /// ([`OverflowPolicy::Checked`] by default)
+    ///
+    /// See the [arithmetic module](crate::arithmetic) for an example.
+    #[inline]
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// Returns what the VM does when an arithmetic instruction overflows
+    #[inline]
+    pub const fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Returns the number of instructions executed by the VM since it was created
+    ///
+    /// Read instructions waiting for an input and halt instructions are not counted.
+    #[inline]
+    pub const fn executed_instructions(&self) -> u64 {
+        self.executed_instructions
+    }
+
+    /// Returns `true` if the VM cannot execute another instruction without going over one of its budgets
+    #[inline]
+    fn budget_exhausted(&self, run_start: u64) -> bool {
+        self.run_budget
+            .is_some_and(|budget| self.executed_instructions - run_start >= budget)
+            || self
+                .total_budget
+                .is_some_and(|budget| self.executed_instructions >= budget)
     }
 
+    /// Returns the [breakpoints](crate::breakpoint) of the VM
     #[inline]
-    fn get_2_after_intr_ptr(&self) -> (&T, &T) {
-        (self.get_at_instr_ptr(1), self.get_at_instr_ptr(2))
+    pub const fn breakpoints(&self) -> &Breakpoints<T> {
+        &self.breakpoints
+    }
+
+    /// Returns the [breakpoints](crate::breakpoint) of the VM, to add or remove some
+    #[inline]
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints<T> {
+        &mut self.breakpoints
     }
 }
 
@@ -268,77 +1288,199 @@ where
     }
 }
 
-mod instr {
+/// [Iterator] over the outputs of a [VM](IntcodeVM),
+/// created by [`vm.outputs()`](IntcodeVM::outputs) and [`vm.outputs_with()`](IntcodeVM::outputs_with)
+#[derive(Debug)]
+pub struct Outputs<'vm, T, I = NoInput, O = NoopObserver, M = Memory<T>>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    vm: &'vm mut IntcodeVM<T, O, M>,
+    input: I,
+    /// Number of instructions executed by the VM when the iterator was created
+    run_start: u64,
+    stop_reason: Option<StopReason>,
+    failed: bool,
+}
+
+impl<T, I, O, M> Outputs<'_, T, I, O, M>
+where
+    T: Integer + Clone + ToPrimitive,
+{
+    /// Returns why the VM stopped once the iterator is exhausted
+    ///
+    /// Returns [`None`] while the VM can still produce outputs, or if the iterator ended on an error.
+    #[inline]
+    pub const fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    /// Returns the [InputSource] given to [`vm.outputs_with()`](IntcodeVM::outputs_with)
+    #[inline]
+    pub fn into_input(self) -> I {
+        self.input
+    }
+}
+
+impl<T, I, O, M> Iterator for Outputs<'_, T, I, O, M>
+where
+    T: Integer + Clone + ToPrimitive + OverflowArithmetic,
+    I: InputSource<T>,
+    O: Observer<T>,
+    M: MemoryBackend<T>,
+{
+    type Item = error::Result<T, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.stop_reason.is_some() {
+            return None;
+        }
+
+        match self.vm.run_inner(&mut self.input, self.run_start) {
+            Ok(VMResult::Output(value)) => Some(Ok(value)),
+            Ok(VMResult::WaitingForInput) => {
+                self.stop_reason = Some(StopReason::WaitingForInput);
+                None
+            }
+            Ok(VMResult::Halted) => {
+                self.stop_reason = Some(StopReason::Halted);
+                None
+            }
+            Ok(VMResult::Breakpoint { address, reason }) => {
+                self.stop_reason = Some(StopReason::Breakpoint { address, reason });
+                None
+            }
+            Ok(VMResult::BudgetExhausted) => {
+                self.stop_reason = Some(StopReason::BudgetExhausted);
+                None
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<T, I, O, M> FusedIterator for Outputs<'_, T, I, O, M>
+where
+    T: Integer + Clone + ToPrimitive + OverflowArithmetic,
+    I: InputSource<T>,
+    O: Observer<T>,
+    M: MemoryBackend<T>,
+{
+}
+
+pub(crate) mod instr {
     use num::{Integer, ToPrimitive};
 
     use crate::{
-        error::{self, VMError},
+        arithmetic::{OverflowArithmetic, OverflowPolicy},
+        error::{self, VMError, VMErrorKind},
+        instruction::{ArgMode, Opcode},
+        memory::MemoryBackend,
         IntcodeVM,
     };
 
-    #[derive(Debug, Clone, Copy)]
-    enum ArgMode {
-        Positional,
-        Immediate,
-        Relative,
-    }
-
     #[derive(Debug, Clone)]
-    pub(super) struct ArgInfo<'t, T> {
+    pub(crate) struct ArgInfo<T> {
         opcode: u16,
         arg_num: u8,
         mode: ArgMode,
-        value: &'t T,
+        value: T,
     }
 
-    impl<'vm, T> ArgInfo<'vm, T>
+    impl<T> ArgInfo<T>
     where
-        T: Integer + Clone + ToPrimitive,
+        T: Integer + Clone + ToPrimitive + OverflowArithmetic,
     {
+        /// Returns the address the argument of the instruction at `instruction_ptr` points to,
+        /// [`None`] for immediate arguments
+        ///
+        /// A relative address that overflows is an error whatever the [OverflowPolicy].
         #[inline]
-        pub(super) fn resolve_value(&self, vm: &'vm IntcodeVM<T>) -> error::Result<&'vm T, T> {
+        pub(super) fn address(
+            &self,
+            instruction_ptr: usize,
+            relative_base: &T,
+        ) -> error::Result<Option<usize>, T> {
             match self.mode {
-                ArgMode::Immediate => Ok(self.value),
-                ArgMode::Positional => Ok(vm.memory.get(
-                    self.value
-                        .to_usize()
-                        .ok_or_else(|| VMError::CannotCastToUsize(self.value.clone()))?,
-                )),
+                ArgMode::Immediate => Ok(None),
+                ArgMode::Positional => self.value.to_usize().map(Some).ok_or_else(|| {
+                    VMError::from(VMErrorKind::CannotCastToUsize(self.value.clone()))
+                        .with_arg(self.arg_num)
+                }),
                 ArgMode::Relative => {
-                    let real_address = self.value.clone() + vm.relative_base_ptr.clone();
-                    Ok(vm.memory.get(
-                        real_address
-                            .to_usize()
-                            .ok_or(VMError::CannotCastToUsize(real_address))?,
-                    ))
+                    let real_address = self
+                        .value
+                        .add_with(relative_base, OverflowPolicy::Checked)
+                        .ok_or_else(|| {
+                            VMError::from(VMErrorKind::ArithmeticOverflow {
+                                ip: instruction_ptr,
+                                opcode: Opcode::from_code(self.opcode % 100)
+                                    .expect("the instruction was decoded"),
+                            })
+                            .with_arg(self.arg_num)
+                        })?;
+                    real_address.to_usize().map(Some).ok_or_else(|| {
+                        VMError::from(VMErrorKind::CannotCastToUsize(real_address))
+                            .with_arg(self.arg_num)
+                    })
                 }
             }
         }
 
+        /// Returns the value the argument resolves to, without going through the VM's hooks
         #[inline]
-        pub(super) fn resolve_address(&self, vm: &'vm IntcodeVM<T>) -> error::Result<usize, T> {
-            match self.mode {
-                ArgMode::Immediate => Err(VMError::ArgModeCannotBeImmediate {
-                    opcode: self.opcode,
-                    arg_num: self.arg_num,
-                }),
-                ArgMode::Positional => self
-                    .value
-                    .to_usize()
-                    .ok_or_else(|| VMError::CannotCastToUsize(self.value.clone())),
-                ArgMode::Relative => {
-                    let real_address = self.value.clone() + vm.relative_base_ptr.clone();
-                    real_address
-                        .to_usize()
-                        .ok_or(VMError::CannotCastToUsize(real_address))
-                }
-            }
+        pub(super) fn resolve_value<M: MemoryBackend<T>>(
+            &self,
+            memory: &M,
+            instruction_ptr: usize,
+            relative_base: &T,
+        ) -> error::Result<T, T> {
+            Ok(match self.address(instruction_ptr, relative_base)? {
+                Some(address) => memory.read(address),
+                None => self.value.clone(),
+            })
+        }
+
+        #[inline]
+        pub(super) fn resolve_address(
+            &self,
+            instruction_ptr: usize,
+            relative_base: &T,
+        ) -> error::Result<usize, T> {
+            self.address(instruction_ptr, relative_base)?
+                .ok_or_else(|| {
+                    VMError::from(VMErrorKind::ArgModeCannotBeImmediate {
+                        opcode: self.opcode,
+                        arg_num: self.arg_num,
+                    })
+                    .with_arg(self.arg_num)
+                })
+        }
+    }
+
+    impl<T> ArgInfo<T> {
+        #[inline]
+        pub(crate) const fn arg_num(&self) -> u8 {
+            self.arg_num
+        }
+
+        #[inline]
+        pub(crate) const fn mode(&self) -> ArgMode {
+            self.mode
+        }
+
+        #[inline]
+        pub(crate) const fn raw(&self) -> &T {
+            &self.value
         }
     }
 
-    impl<'t, T> From<(u16, &'t T, ArgMode, u8)> for ArgInfo<'t, T> {
+    impl<T> From<(u16, T, ArgMode, u8)> for ArgInfo<T> {
         #[inline]
-        fn from(value: (u16, &'t T, ArgMode, u8)) -> Self {
+        fn from(value: (u16, T, ArgMode, u8)) -> Self {
             Self {
                 opcode: value.0,
                 arg_num: value.3,
@@ -349,190 +1491,101 @@ mod instr {
     }
 
     #[derive(Debug, Clone)]
-    pub(super) enum Instruction<'t, T> {
-        Add(ArgInfo<'t, T>, ArgInfo<'t, T>, ArgInfo<'t, T>),
-        Mul(ArgInfo<'t, T>, ArgInfo<'t, T>, ArgInfo<'t, T>),
-        ReadInput(ArgInfo<'t, T>),
-        WriteOutput(ArgInfo<'t, T>),
-        JmpIfTrue(ArgInfo<'t, T>, ArgInfo<'t, T>),
-        JmpIfFalse(ArgInfo<'t, T>, ArgInfo<'t, T>),
-        LessThan(ArgInfo<'t, T>, ArgInfo<'t, T>, ArgInfo<'t, T>),
-        Equals(ArgInfo<'t, T>, ArgInfo<'t, T>, ArgInfo<'t, T>),
-        AddRelativeBase(ArgInfo<'t, T>),
+    pub(crate) enum Instruction<T> {
+        Add(ArgInfo<T>, ArgInfo<T>, ArgInfo<T>),
+        Mul(ArgInfo<T>, ArgInfo<T>, ArgInfo<T>),
+        ReadInput(ArgInfo<T>),
+        WriteOutput(ArgInfo<T>),
+        JmpIfTrue(ArgInfo<T>, ArgInfo<T>),
+        JmpIfFalse(ArgInfo<T>, ArgInfo<T>),
+        LessThan(ArgInfo<T>, ArgInfo<T>, ArgInfo<T>),
+        Equals(ArgInfo<T>, ArgInfo<T>, ArgInfo<T>),
+        AddRelativeBase(ArgInfo<T>),
         Halt,
     }
 
-    impl<'t, T> Instruction<'t, T>
+    impl<T> Instruction<T>
     where
-        T: Integer + Clone + ToPrimitive + 't,
+        T: Integer + Clone + ToPrimitive,
     {
         #[inline]
-        pub(super) fn from_current_instr_ptr(vm: &'t IntcodeVM<T>) -> error::Result<Self, T> {
-            let instr = vm.get_at_instr_ptr(0);
-            let op = instr
-                .to_u16()
-                .ok_or_else(|| VMError::CannotCastToU16(instr.clone()))?;
-
-            let (arg1_mode, arg2_mode, arg3_mode) = Self::get_3_arg_modes(op)?;
-            match op % 100 {
-                1 => Self::create_add(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                2 => Self::create_mul(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                3 => Self::create_read_input(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                4 => Self::create_write_output(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                5 => Self::create_jmp_if_true(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                6 => Self::create_jmp_if_false(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                7 => Self::create_less_than(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                8 => Self::create_equals(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                9 => Self::create_add_relative_base(vm, arg1_mode, arg2_mode, arg3_mode, op),
-                99 => Ok(Self::Halt),
-                other => Err(VMError::UnknownInstruction(other)),
-            }
-        }
-
-        #[inline]
-        pub(super) const fn instruction_width(&self) -> usize {
-            match self {
-                Self::Add(_, _, _) => 4,
-                Self::Mul(_, _, _) => 4,
-                Self::ReadInput(_) => 2,
-                Self::WriteOutput(_) => 2,
-                Self::JmpIfTrue(_, _) => 3,
-                Self::JmpIfFalse(_, _) => 3,
-                Self::LessThan(_, _, _) => 4,
-                Self::Equals(_, _, _) => 4,
-                Self::AddRelativeBase(_) => 2,
-                Self::Halt => 1,
-            }
-        }
-
-        #[inline]
-        fn create_add(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            arg2_mode: ArgMode,
-            arg3_mode: ArgMode,
-            opcode: u16,
+        pub(super) fn from_current_instr_ptr<O, M: MemoryBackend<T>>(
+            vm: &IntcodeVM<T, O, M>,
         ) -> error::Result<Self, T> {
-            let (arg1, arg2, dest) = vm.get_3_after_intr_ptr();
-            Ok(Self::Add(
-                (opcode, arg1, arg1_mode, 1).into(),
-                (opcode, arg2, arg2_mode, 2).into(),
-                (opcode, dest, arg3_mode, 3).into(),
-            ))
+            Self::from_address(&vm.memory, vm.instruction_ptr)
         }
 
+        /// Decodes the instruction at `address` in `memory`
         #[inline]
-        fn create_mul(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            arg2_mode: ArgMode,
-            arg3_mode: ArgMode,
-            opcode: u16,
+        pub(crate) fn from_address<M: MemoryBackend<T>>(
+            memory: &M,
+            address: usize,
         ) -> error::Result<Self, T> {
-            let (arg1, arg2, dest) = vm.get_3_after_intr_ptr();
-            Ok(Self::Mul(
-                (opcode, arg1, arg1_mode, 1).into(),
-                (opcode, arg2, arg2_mode, 2).into(),
-                (opcode, dest, arg3_mode, 3).into(),
-            ))
-        }
-
-        #[inline]
-        fn create_read_input(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            _arg2_mode: ArgMode,
-            _arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let arg = vm.get_at_instr_ptr(1);
-            Ok(Self::ReadInput((opcode, arg, arg1_mode, 1).into()))
-        }
-
-        #[inline]
-        fn create_write_output(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            _arg2_mode: ArgMode,
-            _arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let arg = vm.get_at_instr_ptr(1);
-            Ok(Self::WriteOutput((opcode, arg, arg1_mode, 1).into()))
-        }
+            let instr = memory.read(address);
+            let op = instr
+                .to_u16()
+                .ok_or_else(|| VMErrorKind::CannotCastToU16(instr))?;
 
-        #[inline]
-        fn create_jmp_if_true(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            arg2_mode: ArgMode,
-            _arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let (arg1, target) = vm.get_2_after_intr_ptr();
-            Ok(Self::JmpIfTrue(
-                (opcode, arg1, arg1_mode, 1).into(),
-                (opcode, target, arg2_mode, 2).into(),
-            ))
-        }
+            let (arg1_mode, arg2_mode, arg3_mode) = Self::get_3_arg_modes(op)?;
+            let opcode =
+                Opcode::from_code(op % 100).ok_or(VMErrorKind::UnknownInstruction(op % 100))?;
+            let arg = |arg_num: u8, mode: ArgMode| -> ArgInfo<T> {
+                (op, memory.read(address + arg_num as usize), mode, arg_num).into()
+            };
 
-        #[inline]
-        fn create_jmp_if_false(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            arg2_mode: ArgMode,
-            _arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let (arg1, target) = vm.get_2_after_intr_ptr();
-            Ok(Self::JmpIfFalse(
-                (opcode, arg1, arg1_mode, 1).into(),
-                (opcode, target, arg2_mode, 2).into(),
-            ))
+            Ok(match opcode {
+                Opcode::Add => Self::Add(arg(1, arg1_mode), arg(2, arg2_mode), arg(3, arg3_mode)),
+                Opcode::Mul => Self::Mul(arg(1, arg1_mode), arg(2, arg2_mode), arg(3, arg3_mode)),
+                Opcode::ReadInput => Self::ReadInput(arg(1, arg1_mode)),
+                Opcode::WriteOutput => Self::WriteOutput(arg(1, arg1_mode)),
+                Opcode::JmpIfTrue => Self::JmpIfTrue(arg(1, arg1_mode), arg(2, arg2_mode)),
+                Opcode::JmpIfFalse => Self::JmpIfFalse(arg(1, arg1_mode), arg(2, arg2_mode)),
+                Opcode::LessThan => {
+                    Self::LessThan(arg(1, arg1_mode), arg(2, arg2_mode), arg(3, arg3_mode))
+                }
+                Opcode::Equals => {
+                    Self::Equals(arg(1, arg1_mode), arg(2, arg2_mode), arg(3, arg3_mode))
+                }
+                Opcode::AddRelativeBase => Self::AddRelativeBase(arg(1, arg1_mode)),
+                Opcode::Halt => Self::Halt,
+            })
         }
 
         #[inline]
-        fn create_less_than(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            arg2_mode: ArgMode,
-            arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let (arg1, arg2, dest) = vm.get_3_after_intr_ptr();
-            Ok(Self::LessThan(
-                (opcode, arg1, arg1_mode, 1).into(),
-                (opcode, arg2, arg2_mode, 2).into(),
-                (opcode, dest, arg3_mode, 3).into(),
-            ))
+        pub(crate) const fn opcode(&self) -> Opcode {
+            match self {
+                Self::Add(_, _, _) => Opcode::Add,
+                Self::Mul(_, _, _) => Opcode::Mul,
+                Self::ReadInput(_) => Opcode::ReadInput,
+                Self::WriteOutput(_) => Opcode::WriteOutput,
+                Self::JmpIfTrue(_, _) => Opcode::JmpIfTrue,
+                Self::JmpIfFalse(_, _) => Opcode::JmpIfFalse,
+                Self::LessThan(_, _, _) => Opcode::LessThan,
+                Self::Equals(_, _, _) => Opcode::Equals,
+                Self::AddRelativeBase(_) => Opcode::AddRelativeBase,
+                Self::Halt => Opcode::Halt,
+            }
         }
 
+        /// Returns the arguments of the instruction, in order
         #[inline]
-        fn create_equals(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            arg2_mode: ArgMode,
-            arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let (arg1, arg2, dest) = vm.get_3_after_intr_ptr();
-            Ok(Self::Equals(
-                (opcode, arg1, arg1_mode, 1).into(),
-                (opcode, arg2, arg2_mode, 2).into(),
-                (opcode, dest, arg3_mode, 3).into(),
-            ))
+        pub(crate) fn args(&self) -> Vec<&ArgInfo<T>> {
+            match self {
+                Self::Add(arg1, arg2, arg3)
+                | Self::Mul(arg1, arg2, arg3)
+                | Self::LessThan(arg1, arg2, arg3)
+                | Self::Equals(arg1, arg2, arg3) => vec![arg1, arg2, arg3],
+                Self::JmpIfTrue(arg1, arg2) | Self::JmpIfFalse(arg1, arg2) => vec![arg1, arg2],
+                Self::ReadInput(arg1) | Self::WriteOutput(arg1) | Self::AddRelativeBase(arg1) => {
+                    vec![arg1]
+                }
+                Self::Halt => vec![],
+            }
         }
 
         #[inline]
-        fn create_add_relative_base(
-            vm: &'t IntcodeVM<T>,
-            arg1_mode: ArgMode,
-            _arg2_mode: ArgMode,
-            _arg3_mode: ArgMode,
-            opcode: u16,
-        ) -> error::Result<Self, T> {
-            let arg = vm.get_at_instr_ptr(1);
-            Ok(Self::AddRelativeBase((opcode, arg, arg1_mode, 1).into()))
+        pub(crate) const fn instruction_width(&self) -> usize {
+            self.opcode().arg_count() + 1
         }
 
         #[inline]
@@ -556,11 +1609,12 @@ mod instr {
                 0 => Ok(ArgMode::Positional),
                 1 => Ok(ArgMode::Immediate),
                 2 => Ok(ArgMode::Relative),
-                _ => Err(VMError::InvalidArgMode {
+                _ => Err(VMError::from(VMErrorKind::InvalidArgMode {
                     opcode,
                     arg_num,
                     arg_mode,
-                }),
+                })
+                .with_arg(arg_num)),
             }
         }
     }
@@ -17,15 +17,14 @@ fn part1(input: &str) -> Result<i64, Box<dyn Error>> {
 #[aoc(day02, part2)]
 fn part2(input: &str) -> Result<i64, Box<dyn Error>> {
     const TARGET_RESULT: i64 = 19690720;
-    let memory = input.parse::<Memory<_>>()?;
+    // the program is small and writes all over itself, so a contiguous copy is the cheapest fork
+    let vm = IntcodeVM::new(input.parse::<Memory<_>>()?);
 
     for noun in 0..=99 {
         for verb in 0..=99 {
-            let mut memory = memory.clone();
-            memory.set(1, noun);
-            memory.set(2, verb);
-
-            let mut vm = IntcodeVM::new(memory);
+            let mut vm = vm.fork();
+            vm.memory_mut().set(1, noun);
+            vm.memory_mut().set(2, verb);
             vm.run()?;
 
             if *vm.into_memory().get(0) == TARGET_RESULT {
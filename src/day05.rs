@@ -1,44 +1,32 @@
 use std::error::Error;
 
-use intcode_vm::{IntcodeVM, VMResult};
+use intcode_vm::IntcodeVM;
 
 #[aoc(day05, part1)]
 fn part1(input: &str) -> Result<i64, Box<dyn Error>> {
-    let mut last_diagnostic = 0;
     let mut vm: IntcodeVM<_> = input.parse()?;
-    vm.set_next_input(1);
+    vm.push_input(1);
 
-    loop {
-        match vm.run()? {
-            VMResult::WaitingForInput => {
-                return Err("VM asked for input beyond the `1` already provided".into());
-            }
-            VMResult::Output(diagnostic) => {
-                if last_diagnostic != 0 {
-                    // panics instead of returning an error in case I need to debug (with a breakpoint on panic)
-                    panic!("Last diagnostics wasn't 0 (was {})", last_diagnostic);
-                }
+    let outputs = vm.run_until_halt()?;
+    let (&diagnostic, tests) = outputs
+        .split_last()
+        .ok_or("VM halted without producing any values")?;
 
-                last_diagnostic = diagnostic;
-            }
-            VMResult::Halted => {
-                return Ok(last_diagnostic);
-            }
-        }
+    if let Some(failed) = tests.iter().find(|&&test| test != 0) {
+        Err(format!("Last diagnostics wasn't 0 (was {})", failed))?;
     }
+
+    Ok(diagnostic)
 }
 
 #[aoc(day05, part2)]
 fn part2(input: &str) -> Result<i64, Box<dyn Error>> {
     let mut vm: IntcodeVM<_> = input.parse()?;
-    vm.set_next_input(5);
-
-    let result = match vm.run()? {
-        VMResult::Output(out) => out,
-        VMResult::WaitingForInput => Err("VM asked for input beyond the `5` already provided")?,
-        VMResult::Halted => Err("VM halted without producing any values")?,
-    };
+    vm.push_input(5);
 
-    assert_eq!(vm.run()?, VMResult::Halted);
-    Ok(result)
+    match vm.run_until_halt()?[..] {
+        [out] => Ok(out),
+        [] => Err("VM halted without producing any values")?,
+        ref other => Err(format!("Expected a single output, got {:?}", other))?,
+    }
 }
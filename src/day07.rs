@@ -1,42 +1,42 @@
 use std::{error::Error, num::ParseIntError};
 
-use intcode_vm::{IntcodeVM, VMResult};
+use futures::{channel::mpsc, executor::block_on, future::join_all};
+use intcode_vm::{memory::Memory, pipeline::Pipeline, IntcodeVM, StopReason};
 use itertools::Itertools;
 
+/// Maximum number of instructions an amplifier may execute,
+/// so a wrong program fails instead of looping forever
+const AMPLIFIER_RUN_BUDGET: u64 = 1_000_000;
+
 #[aoc_generator(day07)]
 fn parse(input: &str) -> Result<Vec<i64>, ParseIntError> {
     input.split(',').map(str::parse).collect()
 }
 
+/// Returns the thrust produced by amplifiers running `program` with the given phase settings
+fn thrust(
+    program: &Memory<i64>,
+    settings: Vec<i64>,
+    feedback: bool,
+) -> Result<i64, Box<dyn Error>> {
+    let mut pipeline = Pipeline::with_initial_inputs(program, settings);
+    if feedback {
+        pipeline = pipeline.with_feedback();
+    }
+
+    for vm in pipeline.stages_mut() {
+        vm.set_run_budget(Some(AMPLIFIER_RUN_BUDGET));
+    }
+
+    Ok(pipeline.run([0])?)
+}
+
 #[aoc(day07, part1)]
 fn part1(program: &[i64]) -> Result<i64, Box<dyn Error>> {
+    let program = Memory::from(program.iter().copied());
     (0..=4)
         .permutations(5)
-        .map(|settings| {
-            let mut current_trust = 0;
-            for phase in settings {
-                let mut vm = IntcodeVM::from(program.iter().copied());
-                vm.set_next_input(phase);
-
-                match vm.run()? {
-                    VMResult::WaitingForInput => (),
-                    other => Err(format!("Expected variant WaitingForInput, got {:?}", other))?,
-                }
-
-                vm.set_next_input(current_trust);
-                match vm.run()? {
-                    VMResult::Output(produced) => current_trust = produced,
-                    other => Err(format!("Expected variant Output(value), got {:?}", other))?,
-                }
-
-                match vm.run()? {
-                    VMResult::Halted => (),
-                    other => Err(format!("Expected variant Halted, got {:?}", other))?,
-                }
-            }
-
-            Ok::<_, Box<dyn Error>>(current_trust)
-        })
+        .map(|settings| thrust(&program, settings, false))
         .fold_ok(i64::MIN, std::cmp::max)
 }
 
@@ -46,34 +46,52 @@ fn part2(program: &[i64]) -> Result<i64, Box<dyn Error>> {
         .permutations(5)
         .map(|settings| -> Result<i64, Box<dyn Error>> {
             let mut vms = settings
-                .into_iter()
-                .map(|phase| {
+                .iter()
+                .map(|&phase| {
                     let mut vm = IntcodeVM::from(program.iter().copied());
-                    vm.set_next_input(phase);
+                    vm.push_input(phase);
+                    vm.set_run_budget(Some(AMPLIFIER_RUN_BUDGET));
                     vm
                 })
                 .collect_vec();
 
-            let mut current_thrust = 0;
-            Ok('outer: loop {
-                for vm in vms.iter_mut() {
-                    match vm.run()? {
-                        VMResult::WaitingForInput => (),
-                        VMResult::Halted => break 'outer current_thrust,
-                        VMResult::Output(v) => Err(format!("VM outputed {v} unexpectedly"))?,
-                    }
-
-                    vm.set_next_input(current_thrust);
-                    match vm.run()? {
-                        VMResult::Output(out) => current_thrust = out,
-                        other => Err(format!("Expected variant Output(value), got {:?}", other))?,
-                    }
+            // amplifier i reads from channel i and writes to the next channel,
+            // the last one writes to the first channel
+            let (mut senders, mut receivers): (Vec<_>, Vec<_>) =
+                vms.iter().map(|_| mpsc::unbounded()).unzip();
+            senders[0].unbounded_send(0)?;
+            senders.rotate_left(1);
+
+            let stops = block_on(join_all(
+                vms.iter_mut()
+                    .zip(receivers.iter_mut())
+                    .zip(senders)
+                    .map(|((vm, receiver), sender)| vm.run_async(receiver, sender)),
+            ));
+            for stop in stops {
+                match stop? {
+                    StopReason::Halted => (),
+                    other => Err(format!("Expected the amplifier to halt, got {:?}", other))?,
                 }
-            })
+            }
+
+            // the first amplifier halted before reading the last output
+            Ok(receivers[0]
+                .try_recv()
+                .map_err(|_| "The last amplifier did not output anything")?)
         })
         .fold_ok(i64::MIN, std::cmp::max)
 }
 
+#[aoc(day07, part2, pipeline)]
+fn part2_pipeline(program: &[i64]) -> Result<i64, Box<dyn Error>> {
+    let program = Memory::from(program.iter().copied());
+    (5..=9)
+        .permutations(5)
+        .map(|settings| thrust(&program, settings, true))
+        .fold_ok(i64::MIN, std::cmp::max)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,4 +151,16 @@ mod tests {
             18216
         );
     }
+
+    #[test]
+    fn part2_pipeline_example1() {
+        assert_eq!(
+            part2_pipeline(
+                &parse("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5")
+                .unwrap()
+            )
+            .unwrap(),
+            139629729
+        );
+    }
 }
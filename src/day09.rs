@@ -1,19 +1,16 @@
 use std::error::Error;
 
-use intcode_vm::{IntcodeVM, VMResult};
+use intcode_vm::FastIntcodeVM;
 
 #[inline]
 fn solve(input: &str, part: i64) -> Result<i64, Box<dyn Error>> {
-    let mut vm: IntcodeVM<_> = input.parse()?;
-    vm.set_next_input(part);
+    let mut vm: FastIntcodeVM = input.parse()?;
+    vm.push_input(part);
 
-    let result = match vm.run()? {
-        VMResult::Output(out) => out,
-        other => Err(format!("Expected an output, got {:?}", other))?,
-    };
-
-    assert_eq!(vm.run()?, VMResult::Halted);
-    Ok(result)
+    match vm.run_until_halt()?[..] {
+        [out] => Ok(out),
+        ref other => Err(format!("Expected a single output, got {:?}", other))?,
+    }
 }
 
 #[aoc(day09, part1)]
@@ -1,7 +1,10 @@
 use std::{error::Error, iter};
 
 use fnv::FnvHashMap;
-use intcode_vm::{IntcodeVM, VMResult};
+use intcode_vm::{
+    io::{InputSource, OutputSink},
+    IntcodeVM, StopReason,
+};
 use itertools::Itertools;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
@@ -74,109 +77,106 @@ impl Direction {
     }
 }
 
-#[aoc(day11, part1)]
-fn part1(input: &str) -> Result<usize, Box<dyn Error>> {
-    let mut vm: IntcodeVM<i64> = input.parse()?;
-    let mut painted_map = FnvHashMap::default();
-    let mut current_pos: Position = (0, 0);
-    let mut current_direction = Direction::North;
-
-    loop {
-        match vm.run()? {
-            VMResult::Halted => break,
-            VMResult::WaitingForInput => {
-                vm.set_next_input(painted_map.get(&current_pos).map_or(0, PanelColor::as_i64));
-            }
-            VMResult::Output(out) => panic!("Expected program to ask for input, outputed {}", out),
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum RobotState {
+    #[default]
+    ReadingColor,
+    Painting,
+    Turning,
+}
 
-        match vm.run()? {
-            VMResult::Halted => break,
-            VMResult::WaitingForInput => panic!("Program want another input... but we're waiting for the color to paint the current panel"),
-            VMResult::Output(paint) => {
-                painted_map.insert(
-                    current_pos,
-                    match paint {
-                        0 => PanelColor::Black,
-                        1 => PanelColor::White,
-                        other => panic!("Color to paint was neither 0 nor 1 ({})", other),
-                    },
-                );
-            }
-        }
+#[derive(Debug, Clone, Default)]
+struct Robot {
+    painted_map: FnvHashMap<Position, PanelColor>,
+    current_pos: Position,
+    current_direction: Direction,
+    state: RobotState,
+}
 
-        match vm.run()? {
-            VMResult::Halted => break,
-            VMResult::WaitingForInput => panic!(
+impl InputSource<i64> for Robot {
+    fn next_input(&mut self) -> Option<i64> {
+        match self.state {
+            RobotState::ReadingColor => (),
+            RobotState::Painting => panic!("Program want another input... but we're waiting for the color to paint the current panel"),
+            RobotState::Turning => panic!(
                 "Program want another input... but we're waiting for which direction we turn to"
             ),
-            VMResult::Output(turn) => match turn {
-                0 => {
-                    current_direction = current_direction.turn_left();
-                }
-                1 => {
-                    current_direction = current_direction.turn_right();
-                }
-                other => panic!("Direction to turn to was neither 0 nor 1 ({})", other),
-            },
         }
 
-        current_pos = current_direction.translate_pos(current_pos);
+        self.state = RobotState::Painting;
+        Some(
+            self.painted_map
+                .get(&self.current_pos)
+                .map_or(0, PanelColor::as_i64),
+        )
     }
-
-    Ok(painted_map.len())
 }
 
-#[aoc(day11, part2)]
-fn part2(input: &str) -> Result<String, Box<dyn Error>> {
-    let mut vm: IntcodeVM<i64> = input.parse()?;
-    let mut current_pos: Position = (0, 0);
-    let mut current_direction = Direction::North;
-    let mut painted_map =
-        iter::once((current_pos, PanelColor::White)).collect::<FnvHashMap<_, _>>();
-
-    loop {
-        match vm.run()? {
-            VMResult::Halted => break,
-            VMResult::WaitingForInput => {
-                vm.set_next_input(painted_map.get(&current_pos).map_or(0, PanelColor::as_i64));
+impl OutputSink<i64> for Robot {
+    fn write_output(&mut self, value: i64) {
+        match self.state {
+            RobotState::ReadingColor => {
+                panic!("Expected program to ask for input, outputed {}", value)
             }
-            VMResult::Output(out) => panic!("Expected program to ask for input, outputed {}", out),
-        }
-
-        match vm.run()? {
-            VMResult::Halted => break,
-            VMResult::WaitingForInput => panic!("Program want another input... but we're waiting for the color to paint the current panel"),
-            VMResult::Output(paint) => {
-                painted_map.insert(
-                    current_pos,
-                    match paint {
+            RobotState::Painting => {
+                self.painted_map.insert(
+                    self.current_pos,
+                    match value {
                         0 => PanelColor::Black,
                         1 => PanelColor::White,
                         other => panic!("Color to paint was neither 0 nor 1 ({})", other),
                     },
                 );
+
+                self.state = RobotState::Turning;
+            }
+            RobotState::Turning => {
+                self.current_direction = match value {
+                    0 => self.current_direction.turn_left(),
+                    1 => self.current_direction.turn_right(),
+                    other => panic!("Direction to turn to was neither 0 nor 1 ({})", other),
+                };
+
+                self.current_pos = self.current_direction.translate_pos(self.current_pos);
+                self.state = RobotState::ReadingColor;
             }
         }
+    }
+}
 
-        match vm.run()? {
-            VMResult::Halted => break,
-            VMResult::WaitingForInput => panic!(
-                "Program want another input... but we're waiting for which direction we turn to"
-            ),
-            VMResult::Output(turn) => match turn {
-                0 => {
-                    current_direction = current_direction.turn_left();
-                }
-                1 => {
-                    current_direction = current_direction.turn_right();
-                }
-                other => panic!("Direction to turn to was neither 0 nor 1 ({})", other),
-            },
+impl Robot {
+    #[inline]
+    fn paint(
+        mut self,
+        vm: &mut IntcodeVM<i64>,
+    ) -> Result<FnvHashMap<Position, PanelColor>, Box<dyn Error>> {
+        match vm.run_device(&mut self)? {
+            StopReason::Halted => Ok(self.painted_map),
+            StopReason::WaitingForInput => unreachable!("The robot always provides an input"),
+            other => Err(format!(
+                "Expected the robot to halt, stopped on {:?}",
+                other
+            ))?,
         }
-
-        current_pos = current_direction.translate_pos(current_pos);
     }
+}
+
+#[aoc(day11, part1)]
+fn part1(input: &str) -> Result<usize, Box<dyn Error>> {
+    let mut vm: IntcodeVM<i64> = input.parse()?;
+    let painted_map = Robot::default().paint(&mut vm)?;
+
+    Ok(painted_map.len())
+}
+
+#[aoc(day11, part2)]
+fn part2(input: &str) -> Result<String, Box<dyn Error>> {
+    let mut vm: IntcodeVM<i64> = input.parse()?;
+    let robot = Robot {
+        painted_map: iter::once(((0, 0), PanelColor::White)).collect(),
+        ..Default::default()
+    };
+    let painted_map = robot.paint(&mut vm)?;
 
     let mut result = String::from("\n");
     let row_minmax = painted_map.keys().map(|pos| pos.0).minmax();
@@ -9,7 +9,14 @@ use std::{
 };
 
 use fnv::FnvHashMap;
-use intcode_vm::{memory::Memory, IntcodeVM, VMResult};
+use intcode_vm::{
+    io::NoInput,
+    memory::Memory,
+    observer::Observer,
+    profile::{ProfileReport, Profiler},
+    IntcodeVM, Outputs, StopReason,
+};
+use itertools::Itertools;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Move {
@@ -109,30 +116,10 @@ impl TilePosition {
 enum DecodeDraw {
     Success(TilePosition, TileId),
     ScoreDisplay(i64),
-    WaitingForInput,
-    Halt,
 }
 
 #[inline]
-fn decode_draw(vm: &mut IntcodeVM<i64>) -> Result<DecodeDraw, Box<dyn Error>> {
-    let x = match vm.run()? {
-        VMResult::Output(v) => v,
-        VMResult::WaitingForInput => return Ok(DecodeDraw::WaitingForInput),
-        VMResult::Halted => return Ok(DecodeDraw::Halt),
-    };
-
-    let y = match vm.run()? {
-        VMResult::Output(v) => v,
-        VMResult::WaitingForInput => return Ok(DecodeDraw::WaitingForInput),
-        VMResult::Halted => return Ok(DecodeDraw::Halt),
-    };
-
-    let id = match vm.run()? {
-        VMResult::Output(v) => v,
-        VMResult::WaitingForInput => return Ok(DecodeDraw::WaitingForInput),
-        VMResult::Halted => return Ok(DecodeDraw::Halt),
-    };
-
+fn decode_draw((x, y, id): (i64, i64, i64)) -> Result<DecodeDraw, Box<dyn Error>> {
     Ok(if x == -1 && y == 0 {
         DecodeDraw::ScoreDisplay(id)
     } else {
@@ -140,45 +127,84 @@ fn decode_draw(vm: &mut IntcodeVM<i64>) -> Result<DecodeDraw, Box<dyn Error>> {
     })
 }
 
+/// Decodes every draw instruction until the VM stops, failing on a trailing incomplete one
+#[inline]
+fn decode_draws<O: Observer<i64>>(
+    outputs: &mut Outputs<i64, NoInput, O>,
+) -> Result<Vec<DecodeDraw>, Box<dyn Error>> {
+    itertools::process_results(outputs, |outputs| {
+        let mut triples = outputs.tuples::<(_, _, _)>();
+        let draws = triples
+            .by_ref()
+            .map(decode_draw)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match triples.into_buffer().len() {
+            0 => Ok(draws),
+            len => Err(format!("incomplete draw instruction: {} trailing output(s)", len).into()),
+        }
+    })?
+}
+
 #[aoc(day13, part1)]
 fn part1(input: &str) -> Result<usize, Box<dyn Error>> {
     let mut vm = input.parse::<IntcodeVM<_>>()?;
     let mut tiles = FnvHashMap::default();
+    let mut outputs = vm.outputs();
 
-    loop {
-        match decode_draw(&mut vm)? {
+    for draw in decode_draws(&mut outputs)? {
+        match draw {
             DecodeDraw::Success(pos, id) => tiles.insert(pos, id),
-            DecodeDraw::WaitingForInput => panic!("Sould not be waiting for an input"),
             DecodeDraw::ScoreDisplay(_) => panic!("Score display should not show up"),
-            DecodeDraw::Halt => {
-                return Ok(tiles
-                    .values()
-                    .filter(|id| matches!(id, TileId::Block))
-                    .count())
-            }
         };
     }
+
+    if outputs.stop_reason() == Some(StopReason::WaitingForInput) {
+        panic!("Sould not be waiting for an input");
+    }
+
+    Ok(tiles
+        .values()
+        .filter(|id| matches!(id, TileId::Block))
+        .count())
 }
 
 const CLEAR_TERM: &str = "\x1b[H\x1b[2J\x1b[3J";
 
+/// Inserts the quarters and parses the game
+fn load_game(input: &str) -> Result<IntcodeVM<i64>, Box<dyn Error>> {
+    let mut memory = input.parse::<Memory<_>>()?;
+    memory.set(0, 2);
+    Ok(memory.into())
+}
+
 #[aoc(day13, part2)]
 fn part2(input: &str) -> Result<i64, Box<dyn Error>> {
+    play(&mut load_game(input)?)
+}
+
+/// Same as part 2, giving where the game spent its time instead of the score
+#[aoc(day13, part2, profiled)]
+fn part2_profiled(input: &str) -> Result<ProfileReport, Box<dyn Error>> {
+    let mut vm = load_game(input)?.with_observer(Profiler::default());
+    play(&mut vm)?;
+    Ok(vm.observer().report(10))
+}
+
+/// Plays until every block is broken, returning the final score
+fn play<O: Observer<i64>>(vm: &mut IntcodeVM<i64, O>) -> Result<i64, Box<dyn Error>> {
     #[allow(unused_variables)]
     let is_terminal = stdout().is_terminal();
     let is_terminal = false; // override
-    let mut vm = input.parse::<Memory<_>>()?;
-    vm.set(0, 2);
-
-    let mut vm = vm.into();
     let mut tiles = FnvHashMap::default();
     let mut score = 0;
     let mut last_ball_pos_x = 0;
     let mut last_paddle_pos_x = 0;
 
     loop {
-        loop {
-            match decode_draw(&mut vm)? {
+        let mut outputs = vm.outputs();
+        for draw in decode_draws(&mut outputs)? {
+            match draw {
                 DecodeDraw::Success(pos, id) => {
                     if is_terminal {
                         tiles.insert(pos, id);
@@ -190,24 +216,28 @@ fn part2(input: &str) -> Result<i64, Box<dyn Error>> {
                         last_paddle_pos_x = pos.x;
                     }
                 }
-                DecodeDraw::WaitingForInput => break,
                 DecodeDraw::ScoreDisplay(v) => {
                     score = v;
                 }
-                DecodeDraw::Halt => {
-                    // println!("Score: {}", score);
-                    // println!("{}", draw(&tiles));
-                    return Ok(score);
-                }
             };
         }
 
+        match outputs.stop_reason() {
+            Some(StopReason::Halted) => {
+                // println!("Score: {}", score);
+                // println!("{}", draw(&tiles));
+                return Ok(score);
+            }
+            Some(StopReason::WaitingForInput) => {}
+            other => return Err(format!("the game stopped unexpectedly: {:?}", other).into()),
+        }
+
         let mv = match last_ball_pos_x.cmp(&last_paddle_pos_x) {
             Ordering::Equal => Move::StayStill,
             Ordering::Greater => Move::Right,
             Ordering::Less => Move::Left,
         };
-        vm.set_next_input(mv.into());
+        vm.push_input(mv.into());
 
         if is_terminal {
             let drawn = draw(&tiles);
@@ -240,3 +270,27 @@ fn draw(tiles: &FnvHashMap<TilePosition, TileId>) -> String {
 
     drawn
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_incomplete_draw() {
+        let mut vm: IntcodeVM<i64> = "104,1,104,2,104,1,104,3,99".parse().unwrap();
+        assert!(decode_draws(&mut vm.outputs()).is_err());
+    }
+
+    #[test]
+    fn play_until_halt() {
+        let mut vm: IntcodeVM<i64> = "104,-1,104,0,104,42,99".parse().unwrap();
+        assert_eq!(play(&mut vm).unwrap(), 42);
+    }
+
+    #[test]
+    fn play_stops_on_exhausted_budget() {
+        let mut vm: IntcodeVM<i64> = "1105,1,0".parse().unwrap();
+        vm.set_run_budget(Some(100));
+        assert!(play(&mut vm).is_err());
+    }
+}